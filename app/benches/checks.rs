@@ -0,0 +1,108 @@
+// Check throughput with many clients asking at once.
+//
+// `mutex` is how checks used to work: every check takes the one projector lock
+// and asks the database for new events. `live` reads the view published by the
+// background projector.
+//
+// cargo bench -p app --bench checks
+use std::{path::Path, sync::Arc};
+
+use app::{
+    config::DatabaseConfig,
+    db::Db,
+    event::Metadata,
+    live::Projections,
+    projection::Projector,
+    projector::{RestaurantProjector, Tables, TABLES},
+    store::EventStore,
+    store_source::StoreEventSource,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::{runtime::Runtime, sync::Mutex};
+use toro::Toro;
+
+const N_TABLE: u64 = 100;
+
+fn setup_store(path: &Path) -> Arc<dyn EventStore> {
+    std::fs::remove_file(path).ok();
+    let store = Db::init(path, &DatabaseConfig::default()).unwrap();
+    for table in 0..N_TABLE {
+        let order = format!("new order for table {}: ramen * 2, sushi * 1", table);
+        let toro = Toro::from_toro_string(&order).unwrap();
+        store.insert_event(&toro, &Metadata::default()).unwrap();
+    }
+    Arc::new(store)
+}
+
+fn projector(store: &Arc<dyn EventStore>) -> Projector<RestaurantProjector, StoreEventSource> {
+    let source = StoreEventSource::new(store.clone());
+    let mut projector = Projector::new(RestaurantProjector::new(N_TABLE), source);
+    projector.update().unwrap();
+    projector
+}
+
+fn concurrent_checks(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let path = std::env::temp_dir().join(format!("restaurant_bench_{}.db", std::process::id()));
+    let store = setup_store(&path);
+    let mutex = Arc::new(Mutex::new(projector(&store)));
+    let live = {
+        let _guard = runtime.enter();
+        let mut projections = Projections::new(None);
+        projections.spawn(TABLES, projector(&store));
+        Arc::new(projections)
+    };
+
+    let mut group = c.benchmark_group("concurrent_checks");
+    for n_clients in [1, 10, 100] {
+        group.throughput(Throughput::Elements(n_clients));
+        group.bench_with_input(BenchmarkId::new("mutex", n_clients), &n_clients, |b, &n| {
+            b.to_async(&runtime).iter(|| {
+                let handles: Vec<_> = (0..n)
+                    .map(|i| {
+                        let mutex = mutex.clone();
+                        tokio::spawn(async move {
+                            let mut projector = mutex.lock().await;
+                            projector.update().unwrap();
+                            let tables = &projector.projection;
+                            let table = tables.get_table((i % N_TABLE) as usize).unwrap();
+                            table.items.len()
+                        })
+                    })
+                    .collect();
+                async move {
+                    for h in handles {
+                        h.await.unwrap();
+                    }
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("live", n_clients), &n_clients, |b, &n| {
+            b.to_async(&runtime).iter(|| {
+                let handles: Vec<_> = (0..n)
+                    .map(|i| {
+                        let live = live.clone();
+                        tokio::spawn(async move {
+                            let view = live.view::<Tables>(TABLES).await.unwrap();
+                            let table = view.get_table((i % N_TABLE) as usize).unwrap();
+                            table.items.len()
+                        })
+                    })
+                    .collect();
+                async move {
+                    for h in handles {
+                        h.await.unwrap();
+                    }
+                }
+            })
+        });
+    }
+    group.finish();
+    drop((mutex, live, store));
+    for suffix in ["", "-wal", "-shm"] {
+        std::fs::remove_file(format!("{}{}", path.display(), suffix)).ok();
+    }
+}
+
+criterion_group!(benches, concurrent_checks);
+criterion_main!(benches);
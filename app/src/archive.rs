@@ -0,0 +1,185 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{bail, Result};
+use tracing::info;
+
+use crate::{
+    config::Config,
+    db::Db,
+    event::EventVersion,
+    menu::Menus,
+    projection::{Projection, Projector},
+    projector::{RestaurantProjector, Snapshot},
+    restaurant::Time,
+    stock::Stock,
+    store::EventStore,
+    store_source::StoreEventSource,
+};
+
+pub struct ArchiveReport {
+    // The last archived event. The live database has a snapshot of this version.
+    pub version: EventVersion,
+    pub n_events: usize,
+    pub file: PathBuf,
+}
+
+// Days since 1970-01-01 to (year, month, day).
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// Something like events-2022-10-18.db
+pub fn archive_file_name(before: Time) -> String {
+    let (year, month, day) = civil_from_days((before / 86400) as i64);
+    format!("events-{:04}-{:02}-{:02}.db", year, month, day)
+}
+
+// Replay another projection up to the snapshot and put its part in
+fn add_to_snapshot<P: Projection>(
+    projection: P,
+    source: StoreEventSource,
+    config: &Config,
+    snapshot: &mut Snapshot,
+) -> Result<()> {
+    let mut projector =
+        Projector::new(projection, source).with_page_size(config.projector.page_size);
+    projector.restore_snapshot(snapshot.version)?;
+    projector.update_until(snapshot.version)?;
+    projector.projection.snapshot(snapshot);
+    Ok(())
+}
+
+// Move every event stored before `before` out of the live store into a SQLite file.
+// Returns None if there is nothing to archive.
+pub fn archive_before(
+    store: Arc<dyn EventStore>,
+    config: &Config,
+    before: Time,
+    dir: &Path,
+) -> Result<Option<ArchiveReport>> {
+    let version = match store.get_version_at(before.saturating_sub(1))? {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    info!("Projecting the restaurant up to event {}...", version);
+    let source = StoreEventSource::new(store.clone());
+    let mut projector = Projector::new(
+        RestaurantProjector::new(config.restaurant.n_table)
+            .with_courses(Arc::new(config.restaurant.courses_by_menu())),
+        source.clone(),
+    )
+    .with_page_size(config.projector.page_size);
+    projector.restore_snapshot(version)?;
+    projector.update_until(version)?;
+    let mut snapshot = projector.snapshot();
+    if snapshot.version != version {
+        bail!(
+            "Could only project up to event {} out of {}",
+            snapshot.version,
+            version
+        );
+    }
+    info!("Counting the stock and the menu up to event {}...", version);
+    add_to_snapshot(Stock::new(), source.clone(), config, &mut snapshot)?;
+    add_to_snapshot(Menus::new(), source, config, &mut snapshot)?;
+
+    std::fs::create_dir_all(dir)?;
+    let file = dir.join(archive_file_name(before));
+    info!("Copying events up to {} into {:?}...", version, file);
+    let archive = Db::init(&file, &config.database)?;
+    let mut from_version = 1;
+    loop {
+        let mut rows = store.get_events(from_version, config.projector.page_size)?;
+        rows.retain(|r| r.version <= version);
+        match rows.last() {
+            Some(last) => from_version = last.version + 1,
+            None => break,
+        }
+        archive.import_events(&rows)?;
+    }
+    // Only drop the events once they are safe in the archive.
+    // If we crash before this, running the archive again just overwrites the copies.
+    info!("Removing archived events from the live store...");
+    let n_events = store.compact(&snapshot)?;
+    Ok(Some(ArchiveReport {
+        version,
+        n_events,
+        file,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Metadata;
+    use toro::Toro;
+
+    #[test]
+    fn test_archive_before() {
+        let config = Config::from_toml_string(
+            r###"
+[restaurant]
+table = 3
+menus = ["a"]
+
+[network]
+ip = "1.1.1.1"
+port = 1234
+"###,
+        );
+        let pid = std::process::id();
+        let db_file = std::env::temp_dir().join(format!("restaurant_live_{}.db", pid));
+        let dir = std::env::temp_dir().join(format!("restaurant_archive_{}", pid));
+        std::fs::remove_file(&db_file).ok();
+        let db: Arc<dyn EventStore> = Arc::new(Db::init(&db_file, &config.database).unwrap());
+        for order in ["new order for table 1: a * 3", "cancel for table 1: a * 1"] {
+            let toro = Toro::from_toro_string(order).unwrap();
+            db.insert_event(&toro, &Metadata::default()).unwrap();
+        }
+
+        assert!(archive_before(db.clone(), &config, 0, &dir).unwrap().is_none());
+        let report = archive_before(db.clone(), &config, Time::MAX, &dir).unwrap().unwrap();
+        assert_eq!((report.version, report.n_events), (2, 2));
+        let archived = Db::init(&report.file, &config.database)
+            .unwrap()
+            .get_events(1, 10)
+            .unwrap();
+        assert_eq!(archived.len(), 2);
+        assert_eq!(archived[1].event_toro, "cancel for table 1: a * 1");
+
+        let toro = Toro::from_toro_string("new order for table 2: a * 1").unwrap();
+        db.insert_event(&toro, &Metadata::default()).unwrap();
+        let mut projector = Projector::new(RestaurantProjector::new(3), StoreEventSource::new(db));
+        projector.restore_snapshot(EventVersion::MAX).unwrap();
+        projector.update().unwrap();
+        let tables = &projector.projection;
+        let (version, tables) = (tables.current_version, tables.tables.clone());
+        // Close the database before removing it
+        drop(projector);
+        std::fs::remove_file(&db_file).ok();
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(version, 3);
+        assert_eq!(tables[1].items.get("a").unwrap().quantity, 2);
+        assert_eq!(tables[2].items.get("a").unwrap().quantity, 1);
+    }
+
+    #[test]
+    fn test_archive_file_name() {
+        assert_eq!(archive_file_name(0), "events-1970-01-01.db");
+        assert_eq!(archive_file_name(951782400), "events-2000-02-29.db");
+        assert_eq!(archive_file_name(1666051200 + 86399), "events-2022-10-18.db");
+    }
+}
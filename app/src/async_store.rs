@@ -0,0 +1,173 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::{mpsc, oneshot};
+use toro::Toro;
+use tracing::{debug, error};
+
+use crate::{
+    event::{EventVersion, Metadata},
+    restaurant::Time,
+    store::{EventFilter, EventPage, EventStore},
+};
+
+// Orders waiting for the writer. Senders wait once it is full.
+const WRITE_QUEUE_SIZE: usize = 1024;
+
+// Events of one write are stored together or not at all
+struct Write {
+    events: Vec<(Toro, Metadata)>,
+    done: oneshot::Sender<Result<Vec<EventVersion>>>,
+}
+
+// Keeps the blocking `EventStore` calls off the async workers.
+// Reads run on tokio's blocking pool. Writes queue up for a single writer thread,
+// which stores everything that piled up meanwhile in one transaction.
+#[derive(Clone)]
+pub struct AsyncStore {
+    store: Arc<dyn EventStore>,
+    writes: mpsc::Sender<Write>,
+}
+
+impl AsyncStore {
+    // The writer thread stops once every clone is dropped
+    pub fn new(store: Arc<dyn EventStore>, batch_size: usize) -> Self {
+        let (writes, queue) = mpsc::channel(WRITE_QUEUE_SIZE);
+        let writer_store = store.clone();
+        std::thread::Builder::new()
+            .name("event-writer".into())
+            .spawn(move || write_loop(writer_store, queue, batch_size.max(1)))
+            .expect("Could not start the writer thread");
+        Self { store, writes }
+    }
+
+    // The store itself, for code that is blocking anyway such as projectors
+    pub fn blocking(&self) -> Arc<dyn EventStore> {
+        self.store.clone()
+    }
+
+    // Returns the version the event got once it is committed
+    pub async fn insert_event(&self, toro: Toro, metadata: Metadata) -> Result<EventVersion> {
+        let versions = self.insert_events(vec![(toro, metadata)]).await?;
+        versions
+            .into_iter()
+            .next()
+            .ok_or(anyhow!("The event got no version"))
+    }
+
+    // All or nothing. Returns the versions in the same order as `events`.
+    pub async fn insert_events(
+        &self,
+        events: Vec<(Toro, Metadata)>,
+    ) -> Result<Vec<EventVersion>> {
+        let (done, result) = oneshot::channel();
+        self.writes
+            .send(Write { events, done })
+            .await
+            .map_err(|_| anyhow!("The writer thread is gone"))?;
+        result.await?
+    }
+
+    pub async fn get_event_page(
+        &self,
+        filter: EventFilter,
+        from_version: EventVersion,
+        limit: usize,
+    ) -> Result<EventPage> {
+        self.run(move |store| store.get_event_page(&filter, from_version, limit))
+            .await
+    }
+
+    pub async fn get_version_at(&self, timestamp: Time) -> Result<Option<EventVersion>> {
+        self.run(move |store| store.get_version_at(timestamp)).await
+    }
+
+    async fn run<R, F>(&self, f: F) -> Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&dyn EventStore) -> Result<R> + Send + 'static,
+    {
+        let store = self.store.clone();
+        tokio::task::spawn_blocking(move || f(store.as_ref())).await?
+    }
+}
+
+fn write_loop(store: Arc<dyn EventStore>, mut queue: mpsc::Receiver<Write>, batch_size: usize) {
+    while let Some(first) = queue.blocking_recv() {
+        let mut batch = vec![first];
+        while batch.len() < batch_size {
+            match queue.try_recv() {
+                Ok(write) => batch.push(write),
+                Err(_) => break,
+            }
+        }
+        let mut events = Vec::new();
+        let mut dones = Vec::new();
+        for write in batch.into_iter() {
+            dones.push((write.events.len(), write.done));
+            events.extend(write.events);
+        }
+        debug!("Storing {} events at once", events.len());
+        match store.insert_events(&events) {
+            Ok(versions) => {
+                let mut versions = versions.into_iter();
+                for (n, done) in dones.into_iter() {
+                    // Nobody waiting is fine. The events are stored either way.
+                    done.send(Ok(versions.by_ref().take(n).collect())).ok();
+                }
+            }
+            Err(e) if dones.len() == 1 => {
+                if let Some((_, done)) = dones.into_iter().next() {
+                    done.send(Err(e)).ok();
+                }
+            }
+            // Don't let one bad write fail the rest of the batch
+            Err(e) => {
+                error!("Could not store a batch of {} events. {}", events.len(), e);
+                let mut start = 0;
+                for (n, done) in dones.into_iter() {
+                    done.send(store.insert_events(&events[start..start + n])).ok();
+                    start += n;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_store::MemoryStore;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrent_inserts() {
+        let store = AsyncStore::new(Arc::new(MemoryStore::new()), 8);
+        let handles: Vec<_> = (0..50)
+            .map(|i| {
+                let store = store.clone();
+                tokio::spawn(async move {
+                    let order = format!("new order for table {}: a * 1", i);
+                    let toro = Toro::from_toro_string(&order).unwrap();
+                    store.insert_event(toro, Metadata::default()).await.unwrap()
+                })
+            })
+            .collect();
+        let mut versions = Vec::new();
+        for h in handles {
+            versions.push(h.await.unwrap());
+        }
+        versions.sort();
+        assert_eq!(versions, (1..=50).collect::<Vec<_>>());
+
+        let page = store
+            .get_event_page(EventFilter::default(), 1, 100)
+            .await
+            .unwrap();
+        assert_eq!(page.events.len(), 50);
+        assert_eq!(store.get_version_at(Time::MAX).await.unwrap(), Some(50));
+
+        let toro = |t| Toro::from_toro_string(&format!("check for table {}", t)).unwrap();
+        let events = vec![(toro(1), Metadata::default()), (toro(2), Metadata::default())];
+        assert_eq!(store.insert_events(events).await.unwrap(), vec![51, 52]);
+    }
+}
@@ -1,29 +1,222 @@
-use std::{time::{SystemTime, UNIX_EPOCH}, path::Path};
+use std::{
+    collections::HashMap,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use r2d2::Pool;
-use r2d2_sqlite::{rusqlite::params, SqliteConnectionManager};
+use r2d2::{CustomizeConnection, Pool};
+use r2d2_sqlite::{
+    rusqlite::{self, params, Connection},
+    SqliteConnectionManager,
+};
 
 use anyhow::Result;
 use toro::Toro;
+use tracing::warn;
 
-use crate::event::EventVersion;
+use crate::config::{DatabaseConfig, JournalMode, Synchronous};
+use crate::event::{EventVersion, Metadata};
+use crate::menu::SavedMenus;
+use crate::projector::Snapshot;
+use crate::restaurant::{Item, Price, Table, TableId, Time};
+use crate::store::{EventFilter, EventRow, EventStore};
 
+// SQLite implementation of `EventStore`
 pub struct Db {
     pub pool: Pool<SqliteConnectionManager>,
 }
 
-pub struct EventRow {
-    pub version: u64,
-    pub event_toro: String,
-    pub timestamp: u64,
+// Columns added after the first release. Old databases get them via ALTER TABLE.
+const EXTRA_COLUMNS: [(&str, &str); 6] = [
+    ("actor", "STRING"),
+    ("role", "STRING"),
+    ("device_id", "STRING"),
+    ("correlation_id", "STRING"),
+    ("command", "STRING"),
+    ("table_id", "INTEGER"),
+];
+
+// Snapshot parts added later. Snapshots taken before don't have them.
+const EXTRA_SNAPSHOT_COLUMNS: [(&str, &str); 2] = [
+    ("with_stock", "INTEGER NOT NULL DEFAULT 0"),
+    ("with_menus", "INTEGER NOT NULL DEFAULT 0"),
+];
+
+fn add_missing_columns(conn: &Connection, table: &str, columns: &[(&str, &str)]) -> Result<()> {
+    let existing_columns = conn
+        .prepare(&format!("SELECT name FROM pragma_table_info('{}');", table))?
+        .query_map(params![], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+    for (column, column_type) in columns {
+        if !existing_columns.iter().any(|c| c == column) {
+            conn.execute(
+                &format!("ALTER TABLE {} ADD COLUMN {} {};", table, column, column_type),
+                params![],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn to_event_row(row: &r2d2_sqlite::rusqlite::Row) -> r2d2_sqlite::rusqlite::Result<EventRow> {
+    Ok(EventRow {
+        version: row.get(0)?,
+        event_toro: row.get(1)?,
+        timestamp: row.get(2)?,
+        metadata: Metadata {
+            actor: row.get(3)?,
+            role: row.get(4)?,
+            device_id: row.get(5)?,
+            correlation_id: row.get(6)?,
+        },
+    })
+}
+
+// SQLite integers are signed. Anything larger is as good as the largest one.
+fn saturate(value: u64) -> i64 {
+    value.min(i64::MAX as u64) as i64
+}
+
+fn insert_event(conn: &Connection, toro: &Toro, metadata: &Metadata) -> Result<EventVersion> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backward.")
+        .as_secs();
+    // Versions never go backward, even when every event has been archived.
+    // The archive always leaves a snapshot of the last archived version behind.
+    conn.execute(
+        "INSERT INTO events
+        (version, event_toro, timestamp, actor, role, device_id, correlation_id, command, table_id)
+        VALUES
+        (
+            (SELECT COALESCE(MAX(v), 0) + 1 FROM (
+                SELECT MAX(version) AS v FROM events
+                UNION ALL
+                SELECT MAX(version) FROM snapshots
+            )),
+            ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8
+        );
+        ",
+        params![
+            toro.to_toro_string(),
+            timestamp,
+            metadata.actor,
+            metadata.role,
+            metadata.device_id,
+            metadata.correlation_id,
+            toro.command.to_string(),
+            toro.tables.single()
+        ],
+    )?;
+    // version is the rowid
+    Ok(conn.last_insert_rowid() as EventVersion)
+}
+
+fn insert_snapshot(conn: &Connection, snapshot: &Snapshot) -> Result<()> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backward.")
+        .as_secs();
+    conn.execute(
+        "INSERT INTO snapshots (version, timestamp) VALUES (?1, ?2)
+        ON CONFLICT (version) DO UPDATE SET timestamp = excluded.timestamp;",
+        params![snapshot.version, timestamp],
+    )?;
+    conn.execute(
+        "DELETE FROM snapshot_items WHERE version = ?1;",
+        params![snapshot.version],
+    )?;
+    let mut stmt = conn.prepare(
+        "INSERT INTO snapshot_items
+        (version, table_id, menu, quantity, cooking_time, timestamp)
+        VALUES
+        (?1, ?2, ?3, ?4, ?5, ?6);",
+    )?;
+    for table in snapshot.tables.iter() {
+        for item in table.items.values() {
+            stmt.execute(params![
+                snapshot.version,
+                table.id,
+                item.id,
+                item.quantity,
+                item.cooking_time,
+                item.timestamp
+            ])?;
+        }
+    }
+    // Kept as it is when the snapshot doesn't have it
+    if let Some(stock) = &snapshot.stock {
+        conn.execute(
+            "DELETE FROM snapshot_stock WHERE version = ?1;",
+            params![snapshot.version],
+        )?;
+        let mut stmt = conn.prepare(
+            "INSERT INTO snapshot_stock (version, menu, quantity) VALUES (?1, ?2, ?3);",
+        )?;
+        for (menu, quantity) in stock.iter() {
+            stmt.execute(params![snapshot.version, menu, quantity])?;
+        }
+        conn.execute(
+            "UPDATE snapshots SET with_stock = 1 WHERE version = ?1;",
+            params![snapshot.version],
+        )?;
+    }
+    if let Some(menus) = &snapshot.menus {
+        conn.execute(
+            "DELETE FROM snapshot_menus WHERE version = ?1;",
+            params![snapshot.version],
+        )?;
+        let mut stmt = conn.prepare(
+            "INSERT INTO snapshot_menus (version, menu, price, served) VALUES (?1, ?2, ?3, ?4);",
+        )?;
+        for (menu, price) in menus.served.iter() {
+            stmt.execute(params![snapshot.version, menu, price, true])?;
+        }
+        for menu in menus.gone.iter() {
+            stmt.execute(params![snapshot.version, menu, None::<Price>, false])?;
+        }
+        conn.execute(
+            "UPDATE snapshots SET with_menus = 1 WHERE version = ?1;",
+            params![snapshot.version],
+        )?;
+    }
+    Ok(())
+}
+
+// Applied to every connection the pool opens
+#[derive(Debug)]
+struct Pragmas {
+    journal_mode: JournalMode,
+    synchronous: Synchronous,
+    busy_timeout: Duration,
+}
+
+impl CustomizeConnection<Connection, rusqlite::Error> for Pragmas {
+    fn on_acquire(&self, conn: &mut Connection) -> Result<(), rusqlite::Error> {
+        conn.busy_timeout(self.busy_timeout)?;
+        // journal_mode answers with the mode it ended up in
+        conn.pragma_update_and_check(None, "journal_mode", self.journal_mode.as_str(), |_| {
+            Ok(())
+        })?;
+        conn.pragma_update(None, "synchronous", self.synchronous.as_str())?;
+        Ok(())
+    }
 }
 
 impl Db {
-    pub fn init(filename: impl AsRef<Path>) -> Result<Self> {
+    // Only the pool and SQLite settings of `config` are used. The file is `filename`.
+    pub fn init(filename: impl AsRef<Path>, config: &DatabaseConfig) -> Result<Self> {
         let manager = SqliteConnectionManager::file(filename);
-        let s = Self {
-            pool: Pool::new(manager)?,
-        };
+        let pool = Pool::builder()
+            .max_size(config.pool_size)
+            .connection_timeout(Duration::from_secs(config.connection_timeout))
+            .connection_customizer(Box::new(Pragmas {
+                journal_mode: config.journal_mode,
+                synchronous: config.synchronous,
+                busy_timeout: Duration::from_millis(config.busy_timeout),
+            }))
+            .build(manager)?;
+        let s = Self { pool };
         s.init_table()?;
         Ok(s)
     }
@@ -38,44 +231,373 @@ impl Db {
                 );",
             params![],
         )?;
+        add_missing_columns(&conn, "events", &EXTRA_COLUMNS)?;
+        self.backfill_command_and_table()?;
+        conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS events_table_id ON events (table_id, version);
+            CREATE INDEX IF NOT EXISTS events_command ON events (command, version);
+            CREATE INDEX IF NOT EXISTS events_timestamp ON events (timestamp);
+            CREATE TABLE IF NOT EXISTS snapshots (
+                version INTEGER PRIMARY KEY,
+                timestamp INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS snapshot_items (
+                version INTEGER NOT NULL,
+                table_id INTEGER NOT NULL,
+                menu STRING NOT NULL,
+                quantity INTEGER NOT NULL,
+                cooking_time INTEGER,
+                timestamp INTEGER NOT NULL,
+                PRIMARY KEY (version, table_id, menu)
+            );
+            CREATE TABLE IF NOT EXISTS snapshot_stock (
+                version INTEGER NOT NULL,
+                menu STRING NOT NULL,
+                quantity INTEGER NOT NULL,
+                PRIMARY KEY (version, menu)
+            );
+            CREATE TABLE IF NOT EXISTS snapshot_menus (
+                version INTEGER NOT NULL,
+                menu STRING NOT NULL,
+                price INTEGER,
+                served INTEGER NOT NULL,
+                PRIMARY KEY (version, menu)
+            );",
+        )?;
+        add_missing_columns(&conn, "snapshots", &EXTRA_SNAPSHOT_COLUMNS)?;
         Ok(())
     }
 
-    pub fn insert_event(&self, toro: Toro) -> Result<()> {
+    // Events stored before the command and table_id columns existed only have
+    // the TORO string, so we parse it once and fill the columns in.
+    fn backfill_command_and_table(&self) -> Result<()> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        {
+            let mut select =
+                tx.prepare("SELECT version, event_toro FROM events WHERE command IS NULL;")?;
+            let mut update =
+                tx.prepare("UPDATE events SET command = ?1, table_id = ?2 WHERE version = ?3;")?;
+            let rows = select
+                .query_map(params![], |row| {
+                    Ok((row.get::<_, EventVersion>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            for (version, event_toro) in rows {
+                // Left NULL, so the rest of the log still gets its columns
+                if let (Some(command), table_id) = command_and_table(version, &event_toro) {
+                    update.execute(params![command, table_id, version])?;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    // Put already stored events into this database as they are.
+    // Used for writing archive files.
+    pub fn import_events(&self, rows: &[EventRow]) -> Result<()> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO events
+                (version, event_toro, timestamp, actor, role, device_id, correlation_id, command,
+                    table_id)
+                VALUES
+                (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9);",
+            )?;
+            for row in rows {
+                let (command, table_id) = command_and_table(row.version, &row.event_toro);
+                stmt.execute(params![
+                    row.version,
+                    row.event_toro,
+                    row.timestamp,
+                    row.metadata.actor,
+                    row.metadata.role,
+                    row.metadata.device_id,
+                    row.metadata.correlation_id,
+                    command,
+                    table_id
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+// What goes into the command and table_id columns. Nothing for a legacy row
+// that doesn't parse, which is kept as it is.
+fn command_and_table(
+    version: EventVersion,
+    event_toro: &str,
+) -> (Option<String>, Option<TableId>) {
+    match Toro::from_stored_string(event_toro) {
+        Ok(toro) => (Some(toro.command.to_string()), toro.tables.single()),
+        Err(e) => {
+            warn!("Event {} doesn't parse: {:?}. Error: {}", version, event_toro, e);
+            (None, None)
+        }
+    }
+}
+
+impl EventStore for Db {
+    fn insert_event(&self, toro: &Toro, metadata: &Metadata) -> Result<EventVersion> {
         let conn = self.pool.get()?;
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("time went backward.")
-            .as_secs();
-        conn.execute(
-            "INSERT INTO events
-            (event_toro, timestamp)
-            VALUES
-            (?1, ?2);
+        insert_event(&conn, toro, metadata)
+    }
+
+    fn insert_events(&self, events: &[(Toro, Metadata)]) -> Result<Vec<EventVersion>> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let versions = events
+            .iter()
+            .map(|(toro, metadata)| insert_event(&tx, toro, metadata))
+            .collect::<Result<Vec<_>>>()?;
+        tx.commit()?;
+        Ok(versions)
+    }
+
+    fn get_events(&self, from_version: EventVersion, limit: usize) -> Result<Vec<EventRow>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "
+            SELECT version, event_toro, timestamp, actor, role, device_id, correlation_id
+            FROM events
+            WHERE version >= ?1
+            ORDER BY version
+            LIMIT ?2;
             ",
-            params![toro.to_toro_string(), timestamp],
         )?;
-        Ok(())
+        let rows = stmt
+            .query_map(params![saturate(from_version), limit as i64], to_event_row)?
+            .collect::<Result<Vec<EventRow>, _>>();
+        Ok(rows?)
     }
 
-    pub fn get_events(&self, from_version: EventVersion) -> Result<Vec<EventRow>> {
+    fn query_events(
+        &self,
+        filter: &EventFilter,
+        from_version: EventVersion,
+        limit: Option<usize>,
+    ) -> Result<Vec<EventRow>> {
         let conn = self.pool.get()?;
         let mut stmt = conn.prepare(
             "
-            SELECT version, event_toro, timestamp
+            SELECT version, event_toro, timestamp, actor, role, device_id, correlation_id
             FROM events
-            WHERE version >= ?1;
+            WHERE version >= ?1
+                AND (?2 IS NULL OR actor = ?2)
+                AND (?3 IS NULL OR device_id = ?3)
+                AND (?4 IS NULL OR correlation_id = ?4)
+                AND (?5 IS NULL OR table_id = ?5)
+                AND (?6 IS NULL OR command = ?6)
+                AND (?7 IS NULL OR timestamp >= ?7)
+                AND (?8 IS NULL OR timestamp <= ?8)
+            ORDER BY version
+            LIMIT ?9;
             ",
         )?;
         let rows = stmt
-            .query_map(params![from_version], |row| {
-                Ok(EventRow {
-                    version: row.get(0)?,
-                    event_toro: row.get(1)?,
-                    timestamp: row.get(2)?,
-                })
-            })?
+            .query_map(
+                params![
+                    saturate(from_version),
+                    filter.actor,
+                    filter.device_id,
+                    filter.correlation_id,
+                    filter.table_id,
+                    filter.command,
+                    filter.since.map(saturate),
+                    filter.until.map(saturate),
+                    // A negative limit means no limit in SQLite
+                    limit.map(|l| l as i64).unwrap_or(-1)
+                ],
+                to_event_row,
+            )?
             .collect::<Result<Vec<EventRow>, _>>();
         Ok(rows?)
     }
+
+    fn get_version_at(&self, timestamp: Time) -> Result<Option<EventVersion>> {
+        let conn = self.pool.get()?;
+        let version = conn.query_row(
+            "SELECT MAX(version) FROM events WHERE timestamp <= ?1;",
+            params![saturate(timestamp)],
+            |row| row.get(0),
+        )?;
+        Ok(version)
+    }
+
+    fn save_snapshot(&self, snapshot: &Snapshot) -> Result<()> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        insert_snapshot(&tx, snapshot)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn load_snapshot(&self, until: EventVersion) -> Result<Option<Snapshot>> {
+        let conn = self.pool.get()?;
+        let version: Option<EventVersion> = conn.query_row(
+            "SELECT MAX(version) FROM snapshots WHERE version <= ?1;",
+            params![saturate(until)],
+            |row| row.get(0),
+        )?;
+        let version = match version {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        let mut stmt = conn.prepare(
+            "SELECT table_id, menu, quantity, cooking_time, timestamp
+            FROM snapshot_items
+            WHERE version = ?1
+            ORDER BY table_id;",
+        )?;
+        let items = stmt
+            .query_map(params![version], |row| {
+                let item = Item {
+                    id: row.get(1)?,
+                    quantity: row.get(2)?,
+                    cooking_time: row.get(3)?,
+                    timestamp: row.get(4)?,
+                };
+                Ok((row.get::<_, TableId>(0)?, item))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut tables: Vec<Table> = Vec::new();
+        for (table_id, item) in items {
+            if tables.last().map(|t| t.id) != Some(table_id) {
+                tables.push(Table::new(table_id));
+            }
+            let table = tables.last_mut().expect("just pushed");
+            table.items.insert(item.id.clone(), item);
+        }
+        let (with_stock, with_menus): (bool, bool) = conn.query_row(
+            "SELECT with_stock, with_menus FROM snapshots WHERE version = ?1;",
+            params![version],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let stock = if with_stock {
+            let stock = conn
+                .prepare("SELECT menu, quantity FROM snapshot_stock WHERE version = ?1;")?
+                .query_map(params![version], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<HashMap<_, _>, _>>()?;
+            Some(stock)
+        } else {
+            None
+        };
+        let menus = if with_menus {
+            let mut menus = SavedMenus::default();
+            let mut stmt =
+                conn.prepare("SELECT menu, price, served FROM snapshot_menus WHERE version = ?1;")?;
+            let mut rows = stmt.query(params![version])?;
+            while let Some(row) = rows.next()? {
+                if row.get(2)? {
+                    menus.served.insert(row.get(0)?, row.get(1)?);
+                } else {
+                    menus.gone.insert(row.get(0)?);
+                }
+            }
+            Some(menus)
+        } else {
+            None
+        };
+        Ok(Some(Snapshot {
+            version,
+            tables,
+            stock,
+            menus,
+        }))
+    }
+
+    fn compact(&self, snapshot: &Snapshot) -> Result<usize> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        insert_snapshot(&tx, snapshot)?;
+        let n_deleted = tx.execute(
+            "DELETE FROM events WHERE version <= ?1;",
+            params![saturate(snapshot.version)],
+        )?;
+        tx.commit()?;
+        Ok(n_deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_old_table_gets_metadata_columns() {
+        let path = std::env::temp_dir()
+            .join(format!("restaurant_old_{}.db", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        {
+            let conn = r2d2_sqlite::rusqlite::Connection::open(&path).unwrap();
+            conn.execute(
+                "CREATE TABLE events (
+                    version INTEGER PRIMARY KEY,
+                    event_toro STRING NOT NULL,
+                    timestamp INTEGER NOT NULL
+                );",
+                params![],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO events (event_toro, timestamp) VALUES ('yeet', 1);",
+                params![],
+            )
+            .unwrap();
+            // Some very old row nobody can read anymore
+            conn.execute(
+                "INSERT INTO events (event_toro, timestamp) VALUES ('dance for table 1', 2);",
+                params![],
+            )
+            .unwrap();
+        }
+        let db = Db::init(&path, &DatabaseConfig::default()).unwrap();
+        let rows = db.get_events(1, 10).unwrap();
+        let yeets = db
+            .find_events(&EventFilter {
+                command: Some("yeet".into()),
+                ..Default::default()
+            })
+            .unwrap();
+        drop(db);
+        std::fs::remove_file(&path).ok();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].metadata, Metadata::default());
+        assert_eq!(yeets.len(), 1);
+    }
+
+    #[test]
+    fn test_pragmas_applied() {
+        let path = std::env::temp_dir()
+            .join(format!("restaurant_pragmas_{}.db", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let config = DatabaseConfig {
+            synchronous: Synchronous::Full,
+            busy_timeout: 1234,
+            ..Default::default()
+        };
+        let db = Db::init(&path, &config).unwrap();
+        let conn = db.pool.get().unwrap();
+        let pragma = |name: &str| -> i64 {
+            conn.query_row(&format!("PRAGMA {};", name), params![], |row| row.get(0))
+                .unwrap()
+        };
+        let synchronous = pragma("synchronous");
+        let busy_timeout = pragma("busy_timeout");
+        let journal_mode: String = conn
+            .query_row("PRAGMA journal_mode;", params![], |row| row.get(0))
+            .unwrap();
+        drop(conn);
+        drop(db);
+        std::fs::remove_file(&path).ok();
+        assert_eq!(journal_mode, "wal");
+        // FULL is 2
+        assert_eq!(synchronous, 2);
+        assert_eq!(busy_timeout, 1234);
+    }
 }
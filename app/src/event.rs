@@ -1,6 +1,7 @@
-use crate::restaurant::{Item, TableId, Time};
+use crate::restaurant::{Course, Item, Menu, Price, Sets, TableId, Time};
 
 use anyhow::{anyhow, bail, Result};
+use serde_derive::Serialize;
 
 // Starts from 1
 pub type EventVersion = u64;
@@ -8,12 +9,38 @@ pub type EventVersion = u64;
 pub use toro::Command;
 use toro::{Toro, Param};
 
+// Who and what produced an event. Every field is optional since old events
+// don't have any of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Metadata {
+    // Name of the authenticated token
+    pub actor: Option<String>,
+    pub role: Option<String>,
+    // Supplied by the client via the `X-Device-Id` header
+    pub device_id: Option<String>,
+    // Supplied by the client via the `X-Correlation-Id` header
+    pub correlation_id: Option<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Event {
     pub version: EventVersion,
     pub command: Command,
     pub payload: Option<Payload>,
     pub created: Time,
+    pub metadata: Metadata,
+    // Why items got cancelled, if the staff said so
+    pub reason: Option<String>,
+    // Menus a restock or sold out is about. Empty for other commands.
+    pub stock: Vec<Item>,
+    pub menu_change: Option<MenuChange>,
+    // Courses of a new order that wait to be fired
+    pub hold: Vec<Course>,
+    // The course a fire is for
+    pub course: Option<Course>,
+    // Set menus as they were ordered or cancelled, for the bill.
+    // The payload has their components as they were when the event was stored.
+    pub sets: Vec<Item>,
 }
 
 impl Event {
@@ -23,15 +50,57 @@ impl Event {
             command,
             payload: None,
             created: time,
+            metadata: Metadata::default(),
+            reason: None,
+            stock: Vec::new(),
+            menu_change: None,
+            hold: Vec::new(),
+            course: None,
+            sets: Vec::new(),
         }
     }
 
     pub fn from_toro(toro: &Toro, version: EventVersion, timestamp: Time) -> Result<Self> {
         let command = toro.command;
-        let table_id = toro.table_id.ok_or(anyhow!("Expecting table id"))?;
+        let stock = match (command, &toro.param) {
+            (Command::Restock, Some(Param::MenuQuantities(v))) => {
+                v.iter().map(|mq| Item::new(mq.0.clone(), mq.1, timestamp)).collect()
+            }
+            (Command::SoldOut, Some(Param::Menu(v))) => {
+                v.iter().map(|m| Item::new(m.clone(), 0, timestamp)).collect()
+            }
+            (Command::Restock | Command::SoldOut, _) => {
+                bail!("This Toro doesn't make sense: {}", toro.to_toro_string())
+            }
+            _ => Vec::new(),
+        };
+        let menu_change = match (command, &toro.param) {
+            (Command::AddMenu, Some(Param::Menu(v))) => Some(MenuChange::Add(v.clone())),
+            (Command::RenameMenu, Some(Param::Rename(from, to))) => {
+                Some(MenuChange::Rename(from.clone(), to.clone()))
+            }
+            (Command::RetireMenu, Some(Param::Menu(v))) => Some(MenuChange::Retire(v.clone())),
+            (Command::SetPrice, Some(Param::Prices(v))) => Some(MenuChange::Price(v.clone())),
+            (c, _) if c.edits_menu() => {
+                bail!("This Toro doesn't make sense: {}", toro.to_toro_string())
+            }
+            _ => None,
+        };
+        let hold = toro
+            .hold
+            .iter()
+            .map(|c| c.parse())
+            .collect::<Result<Vec<Course>>>()?;
+        let course = toro.course.as_ref().map(|c| c.parse()).transpose()?;
         let payload = match command {
-            Command::Yeet => None,
+            Command::Yeet | Command::Restock | Command::SoldOut => None,
+            _ if menu_change.is_some() => None,
+            Command::Fire => {
+                let table_id = toro.tables.single().ok_or(anyhow!("Expecting one table"))?;
+                Some(Payload::new(table_id, Vec::new()))
+            }
             Command::New | Command::Cancel => {
+                let table_id = toro.tables.single().ok_or(anyhow!("Expecting one table"))?;
                 let items = match &toro.param {
                     Some(Param::MenuQuantities(v)) => {
                         v.iter().map(|mq| Item::new(mq.0.clone(), mq.1, timestamp)).collect()
@@ -50,6 +119,13 @@ impl Event {
             command,
             payload,
             created: timestamp,
+            metadata: Metadata::default(),
+            reason: toro.reason.clone(),
+            stock,
+            menu_change,
+            hold,
+            course,
+            sets: toro.sets.iter().map(|s| Item::new(s.0.clone(), s.1, timestamp)).collect(),
         })
     }
 
@@ -57,6 +133,91 @@ impl Event {
         self.payload = Some(payload);
         self
     }
+
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn with_reason(mut self, reason: &str) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+
+    pub fn with_stock(mut self, stock: Vec<Item>) -> Self {
+        self.stock = stock;
+        self
+    }
+
+    pub fn with_hold(mut self, hold: Vec<Course>) -> Self {
+        self.hold = hold;
+        self
+    }
+
+    pub fn with_course(mut self, course: Course) -> Self {
+        self.course = Some(course);
+        self
+    }
+
+    // Events stored before quantities were checked can order, cancel or restock
+    // zero or less. `Projector` skips those instead of counting backwards.
+    pub fn bad_quantity(&self) -> Option<&Item> {
+        let items = self.payload.iter().flat_map(|p| p.items.iter());
+        // Sold out is stored as zero on purpose
+        let restocked = self.stock.iter().filter(|_| matches!(self.command, Command::Restock));
+        items.chain(restocked).find(|i| i.quantity <= 0)
+    }
+
+}
+
+// Orders and cancels for the projection tests. Everything in it happens at `time`.
+#[cfg(test)]
+impl Event {
+    pub fn order(
+        version: EventVersion,
+        command: Command,
+        time: Time,
+        table_id: TableId,
+        items: &[(&str, crate::restaurant::Quantity)],
+    ) -> Self {
+        let items = items
+            .iter()
+            .map(|(m, q)| Item::new(m.to_string(), *q, time))
+            .collect();
+        Event::new(version, command, time).with_payload(Payload::new(table_id, items))
+    }
+}
+
+// Replaces every set menu in an order or a cancel with its components before it is stored,
+// so replaying it doesn't depend on what the sets are by then. The sets go along for the bill.
+pub fn expand_sets(mut toro: Toro, sets: &Sets) -> Toro {
+    if !matches!(toro.command, Command::New | Command::Cancel) {
+        return toro;
+    }
+    let items = match &mut toro.param {
+        Some(Param::MenuQuantities(v)) if v.iter().any(|(m, _)| sets.contains_key(m)) => v,
+        _ => return toro,
+    };
+    for (menu, quantity) in std::mem::take(items).into_iter() {
+        match sets.get(&menu) {
+            Some(components) => {
+                items.extend(components.iter().map(|c| (c.clone(), quantity)));
+                toro.sets.push((menu, quantity));
+            }
+            None => items.push((menu, quantity)),
+        }
+    }
+    toro
+}
+
+// A change to what the restaurant serves
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuChange {
+    Add(Vec<Menu>),
+    // From, to
+    Rename(Menu, Menu),
+    Retire(Vec<Menu>),
+    Price(Vec<(Menu, Price)>),
 }
 
 #[derive(Debug, Clone)]
@@ -70,3 +231,97 @@ impl Payload {
         Self { table_id, items }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::restaurant::Quantity;
+
+    #[test]
+    fn test_from_toro() {
+        let toro = Toro::from_toro_string("new order for table 2: a * 3").unwrap();
+        let event = Event::from_toro(&toro, 1, 100).unwrap();
+        let payload = event.payload.unwrap();
+        assert_eq!(payload.table_id, 2);
+        assert_eq!(payload.items[0].quantity, 3);
+
+        // Yeet is for every table at once
+        let toro = Toro::from_toro_string("yeet").unwrap();
+        let event = Event::from_toro(&toro, 2, 100).unwrap();
+        assert!(matches!(event.command, Command::Yeet));
+        assert!(event.payload.is_none());
+
+        let toro = Toro::from_toro_string("cancel for table 2: a * 1 because too slow").unwrap();
+        let event = Event::from_toro(&toro, 3, 100).unwrap();
+        assert_eq!(event.reason.as_deref(), Some("too slow"));
+
+        let toro = Toro::from_toro_string("sold out: a, b").unwrap();
+        let event = Event::from_toro(&toro, 4, 100).unwrap();
+        assert!(event.payload.is_none());
+        assert_eq!(event.stock.len(), 2);
+        assert_eq!(event.stock[1].quantity, 0);
+
+        let toro = Toro::from_toro_string("rename menu: a to b").unwrap();
+        let event = Event::from_toro(&toro, 5, 100).unwrap();
+        assert_eq!(event.menu_change, Some(MenuChange::Rename("a".into(), "b".into())));
+
+        let toro = Toro::from_toro_string("new order for table 2: a * 1 hold mains").unwrap();
+        let event = Event::from_toro(&toro, 6, 100).unwrap();
+        assert_eq!(event.hold, vec![Course::Main]);
+        let toro = Toro::from_toro_string("fire course drinks for table 2").unwrap();
+        let event = Event::from_toro(&toro, 7, 100).unwrap();
+        assert_eq!(event.course, Some(Course::Drink));
+        assert_eq!(event.payload.unwrap().table_id, 2);
+        let toro = Toro::from_toro_string("fire course snacks for table 2").unwrap();
+        assert!(Event::from_toro(&toro, 7, 100).is_err());
+
+        let toro = Toro::from_toro_string("check for table 2").unwrap();
+        assert!(Event::from_toro(&toro, 3, 100).is_err());
+    }
+
+    #[test]
+    fn test_expand_sets() {
+        let sets: Sets = [("ramen set".to_string(), vec!["ramen".into(), "tea".into()])]
+            .into_iter()
+            .collect();
+        let toro = Toro::from_toro_string("cancel for table 1: ramen set * 2, tea * 1").unwrap();
+        let toro = expand_sets(toro, &sets);
+        assert_eq!(
+            toro.to_toro_string(),
+            "cancel for table 1: ramen * 2, tea * 2, tea * 1 with sets ramen set * 2"
+        );
+        let event = Event::from_toro(&toro, 1, 100).unwrap();
+        let items: Vec<(&str, Quantity)> = event
+            .payload
+            .as_ref()
+            .unwrap()
+            .items
+            .iter()
+            .map(|i| (i.id.as_str(), i.quantity))
+            .collect();
+        assert_eq!(items, vec![("ramen", 2), ("tea", 2), ("tea", 1)]);
+        assert_eq!(event.sets.len(), 1);
+        assert_eq!(event.sets[0].id, "ramen set");
+        assert_eq!(event.sets[0].quantity, 2);
+
+        // Nothing to expand
+        let toro = Toro::from_toro_string("new order for table 1: tea * 1").unwrap();
+        let event = Event::from_toro(&expand_sets(toro, &sets), 2, 100).unwrap();
+        assert!(event.sets.is_empty());
+        assert_eq!(event.payload.unwrap().items.len(), 1);
+        let toro = Toro::from_toro_string("check for table 1: ramen set").unwrap();
+        assert!(expand_sets(toro, &sets).sets.is_empty());
+    }
+
+    #[test]
+    fn test_bad_quantity() {
+        let event = |s| Event::from_toro(&Toro::from_stored_string(s).unwrap(), 1, 100).unwrap();
+        assert!(event("new order for table 1: a * 1, b * 2").bad_quantity().is_none());
+        assert!(event("sold out: a").bad_quantity().is_none());
+        assert!(event("fire course mains for table 1").bad_quantity().is_none());
+        let negative = event("new order for table 1: a * 1, b * -5");
+        assert_eq!(negative.bad_quantity().map(|i| i.id.as_str()), Some("b"));
+        assert!(event("cancel for table 1: a * 0").bad_quantity().is_some());
+        assert!(event("restock: a * -1").bad_quantity().is_some());
+    }
+}
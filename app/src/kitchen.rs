@@ -0,0 +1,307 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use anyhow::{bail, Result};
+use serde_derive::Serialize;
+
+use crate::{
+    event::{Command, Event, EventVersion, Payload},
+    projection::Projection,
+    projector::Snapshot,
+    restaurant::{CookingTimeEstimator, Course, Courses, Item, Menu, Quantity, TableId, Time},
+};
+
+// Name of the kitchen queue among the live projections
+pub const KITCHEN: &str = "kitchen";
+
+// One line of an order waiting for the kitchen
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QueueItem {
+    pub table_id: TableId,
+    pub menu: Menu,
+    pub quantity: Quantity,
+    pub ordered_at: Time,
+    pub cooking_time: Time,
+}
+
+impl QueueItem {
+    pub fn ready_at(&self) -> Time {
+        self.ordered_at + self.cooking_time
+    }
+}
+
+// Everything ordered and not cancelled yet, oldest order first
+#[derive(Debug, Clone, Default)]
+pub struct KitchenQueue(pub Arc<VecDeque<QueueItem>>);
+
+impl KitchenQueue {
+    pub fn iter(&self) -> impl Iterator<Item = &QueueItem> {
+        self.0.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+// Fed by the same events as `RestaurantProjector`, but keeps one queue for the
+// whole restaurant instead of one list per table
+#[derive(Default)]
+pub struct Kitchen {
+    version: EventVersion,
+    // Shared with the views handed out to readers
+    queue: Arc<VecDeque<QueueItem>>,
+    // Held courses wait here until they are fired
+    held: Vec<QueueItem>,
+    courses: Arc<Courses>,
+}
+
+impl Kitchen {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_courses(mut self, courses: Arc<Courses>) -> Self {
+        self.courses = courses;
+        self
+    }
+
+    fn process_new_cmd(&mut self, payload: &Payload, hold: &[Course], version: EventVersion) {
+        let queue = Arc::make_mut(&mut self.queue);
+        for item in payload.items.iter() {
+            let queued = QueueItem {
+                table_id: payload.table_id,
+                menu: item.id.clone(),
+                quantity: item.quantity,
+                ordered_at: item.timestamp,
+                cooking_time: CookingTimeEstimator::estimate(item, version),
+            };
+            // Like the tables, an item already cooking isn't held back
+            let cooking = queue
+                .iter()
+                .any(|q| q.table_id == queued.table_id && q.menu == queued.menu);
+            let held = self.courses.get(&item.id).is_some_and(|c| hold.contains(c));
+            if held && !cooking {
+                self.held.push(queued);
+            } else {
+                queue.push_back(queued);
+            }
+        }
+    }
+
+    // The course of the table joins the queue as if it was ordered just now
+    fn process_fire(
+        &mut self,
+        table_id: TableId,
+        course: Course,
+        time: Time,
+        version: EventVersion,
+    ) {
+        let courses = &self.courses;
+        let (fired, held): (Vec<_>, Vec<_>) = self
+            .held
+            .drain(..)
+            .partition(|h| h.table_id == table_id && courses.get(&h.menu) == Some(&course));
+        self.held = held;
+        if fired.is_empty() {
+            return;
+        }
+        let queue = Arc::make_mut(&mut self.queue);
+        for mut item in fired.into_iter() {
+            item.ordered_at = time;
+            let estimated = Item::new(item.menu.clone(), item.quantity, time);
+            item.cooking_time = CookingTimeEstimator::estimate(&estimated, version);
+            queue.push_back(item);
+        }
+    }
+
+    // The latest orders are taken back first, so earlier ones keep their place.
+    // Whatever is still held goes before anything in the queue.
+    fn process_cancel(&mut self, payload: &Payload) {
+        let queue = Arc::make_mut(&mut self.queue);
+        for item in payload.items.iter() {
+            let mut left = item.quantity;
+            for queued in self.held.iter_mut().rev().chain(queue.iter_mut().rev()) {
+                if left <= 0 {
+                    break;
+                }
+                if queued.table_id == payload.table_id && queued.menu == item.id {
+                    let taken = left.min(queued.quantity);
+                    queued.quantity -= taken;
+                    left -= taken;
+                }
+            }
+        }
+        queue.retain(|q| q.quantity > 0);
+        self.held.retain(|q| q.quantity > 0);
+    }
+}
+
+impl Projection for Kitchen {
+    type View = KitchenQueue;
+
+    fn version(&self) -> EventVersion {
+        self.version
+    }
+
+    fn handle(&mut self, event: &Event) -> Result<()> {
+        match (event.command, &event.payload) {
+            (Command::New, Some(payload)) => {
+                self.process_new_cmd(payload, &event.hold, event.version)
+            }
+            (Command::Cancel, Some(payload)) => self.process_cancel(payload),
+            (Command::Fire, Some(payload)) => match event.course {
+                Some(course) => {
+                    self.process_fire(payload.table_id, course, event.created, event.version)
+                }
+                None => bail!("No course to fire"),
+            },
+            (Command::New | Command::Cancel | Command::Fire, None) => {
+                bail!("No payload available")
+            }
+            (Command::Yeet, _) => {
+                self.queue = Arc::default();
+                self.held.clear();
+            }
+            _ => (),
+        }
+        self.version = event.version;
+        Ok(())
+    }
+
+    fn skip(&mut self, event: &Event) {
+        self.version = event.version;
+    }
+
+    fn view(&self) -> KitchenQueue {
+        KitchenQueue(self.queue.clone())
+    }
+
+    // Each table only remembers the total of every item, so whatever a table has
+    // becomes one line per item, ordered when its latest order came in.
+    // Items without a cooking time are still held.
+    fn restore(&mut self, snapshot: &Snapshot) -> Result<bool> {
+        let mut queue = Vec::new();
+        let mut held = Vec::new();
+        for t in snapshot.tables.iter() {
+            for i in t.items.values() {
+                let item = QueueItem {
+                    table_id: t.id,
+                    menu: i.id.clone(),
+                    quantity: i.quantity,
+                    ordered_at: i.timestamp,
+                    cooking_time: i.cooking_time.unwrap_or_default(),
+                };
+                match i.cooking_time {
+                    Some(_) => queue.push(item),
+                    None => held.push(item),
+                }
+            }
+        }
+        queue.sort_by(|a, b| {
+            (a.ordered_at, a.table_id, &a.menu).cmp(&(b.ordered_at, b.table_id, &b.menu))
+        });
+        self.queue = Arc::new(queue.into());
+        self.held = held;
+        self.version = snapshot.version;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::restaurant::Table;
+
+    fn lines(kitchen: &Kitchen) -> Vec<(TableId, &str, Quantity)> {
+        kitchen
+            .queue
+            .iter()
+            .map(|q| (q.table_id, q.menu.as_str(), q.quantity))
+            .collect()
+    }
+
+    #[test]
+    fn test_queue_order() {
+        let mut kitchen = Kitchen::new();
+        let events = [
+            Event::order(1, Command::New, 1, 1, &[("a", 2), ("b", 1)]),
+            Event::order(2, Command::New, 2, 0, &[("a", 1)]),
+            Event::order(3, Command::New, 3, 1, &[("a", 3)]),
+            // Takes back the 3 from the latest order and 1 of the first one
+            Event::order(4, Command::Cancel, 4, 1, &[("a", 4)]),
+            // Table 2 never had any
+            Event::order(5, Command::Cancel, 5, 2, &[("a", 1)]),
+        ];
+        for event in events.iter() {
+            kitchen.handle(event).unwrap();
+        }
+        assert_eq!(kitchen.version(), 5);
+        assert_eq!(lines(&kitchen), vec![(1, "a", 1), (1, "b", 1), (0, "a", 1)]);
+        let first = kitchen.view().iter().next().cloned().unwrap();
+        assert_eq!(first.ordered_at, 1);
+        assert_eq!(first.ready_at(), 1 + first.cooking_time);
+
+        kitchen.handle(&Event::new(6, Command::Yeet, 6)).unwrap();
+        assert!(kitchen.view().is_empty());
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut table = Table::new(3);
+        table.items.insert(
+            "b".into(),
+            Item::new("b".into(), 2, 20).with_cooking_time(60),
+        );
+        table.items.insert(
+            "a".into(),
+            Item::new("a".into(), 1, 10).with_cooking_time(60),
+        );
+        // Still held
+        table.items.insert("c".into(), Item::new("c".into(), 1, 5));
+        let snapshot = Snapshot {
+            version: 7,
+            tables: vec![table],
+            stock: None,
+            menus: None,
+        };
+        let mut kitchen = Kitchen::new();
+        assert!(kitchen.restore(&snapshot).unwrap());
+        assert_eq!(kitchen.version(), 7);
+        assert_eq!(lines(&kitchen), vec![(3, "a", 1), (3, "b", 2)]);
+        assert_eq!(kitchen.held.len(), 1);
+    }
+
+    #[test]
+    fn test_hold_and_fire() {
+        let courses: Courses = [("soup", Course::Starter), ("ramen", Course::Main)]
+            .into_iter()
+            .map(|(m, c)| (m.to_string(), c))
+            .collect();
+        let mut kitchen = Kitchen::new().with_courses(Arc::new(courses));
+        let fire = |version, table_id, course| {
+            Event::new(version, Command::Fire, version * 10)
+                .with_payload(Payload::new(table_id, vec![]))
+                .with_course(course)
+        };
+        let events = [
+            Event::order(1, Command::New, 1, 1, &[("soup", 1), ("ramen", 2)])
+                .with_hold(vec![Course::Main]),
+            Event::order(2, Command::New, 2, 2, &[("ramen", 1)]).with_hold(vec![Course::Main]),
+            Event::order(3, Command::Cancel, 3, 1, &[("ramen", 1)]),
+            // Another table's mains stay held
+            fire(4, 1, Course::Main),
+        ];
+        for event in events.iter().take(3) {
+            kitchen.handle(event).unwrap();
+        }
+        assert_eq!(lines(&kitchen), vec![(1, "soup", 1)]);
+        kitchen.handle(&events[3]).unwrap();
+        assert_eq!(lines(&kitchen), vec![(1, "soup", 1), (1, "ramen", 1)]);
+        let fired = kitchen.view().iter().last().cloned().unwrap();
+        assert_eq!(fired.ordered_at, 40);
+        assert_eq!(kitchen.held.len(), 1);
+
+        kitchen.handle(&Event::new(5, Command::Yeet, 50)).unwrap();
+        assert!(kitchen.held.is_empty());
+    }
+}
@@ -1,57 +1,161 @@
+pub mod archive;
+pub mod async_store;
 pub mod config;
 pub mod db;
 pub mod event;
+pub mod kitchen;
+pub mod live;
+pub mod memory_store;
+pub mod menu;
+pub mod postgres_db;
+pub mod projection;
 pub mod projector;
 pub mod restaurant;
+pub mod sales;
 pub mod service;
-pub mod sql_source;
+pub mod stock;
+pub mod store;
+pub mod store_source;
 
 use std::convert::Infallible;
 use std::net::SocketAddr;
+use std::path::Path;
 use std::str::FromStr;
 use std::sync::Arc;
-
-use tokio::sync::Mutex;
+use std::time::Duration;
 
 use hyper::service::{make_service_fn, service_fn};
 use hyper::Server;
 
 use tracing::{error, info};
 
-use crate::config::Config;
+use crate::archive::archive_before;
+use crate::async_store::AsyncStore;
+use crate::config::{Backend, Config};
 use crate::db::Db;
-use crate::projector::RestaurantProjector;
+use crate::event::{EventVersion, Metadata};
+use crate::kitchen::{Kitchen, KITCHEN};
+use crate::live::Projections;
+use crate::memory_store::MemoryStore;
+use crate::menu::{Menus, MENU};
+use crate::postgres_db::PostgresDb;
+use crate::projection::{Projection, Projector};
+use crate::projector::{RestaurantProjector, TABLES};
+use crate::restaurant::Time;
+use crate::sales::{Sales, SALES};
 use crate::service::restaurant_service;
-use crate::sql_source::SqliteEventSource;
+use crate::stock::{Stock, STOCK};
+use crate::store::EventStore;
+use crate::store_source::StoreEventSource;
+
+// Sets up one projection when the service starts. See `App::with_projection`.
+type MakeProjection = Box<dyn Fn(&Config, Arc<dyn EventStore>, &mut Projections) + Send + Sync>;
 
 pub struct App {
     config: Arc<Config>,
-    db_file: String,
+    projections: Vec<MakeProjection>,
 }
 
 impl App {
-    pub fn new(config: Config, db_file: String) -> Self {
+    pub fn new(config: Config) -> Self {
         Self {
             config: Arc::new(config),
-            db_file,
+            projections: Vec::new(),
+        }
+        .with_projection(KITCHEN, |config| {
+            Kitchen::new().with_courses(Arc::new(config.restaurant.courses_by_menu()))
+        })
+        .with_projection(SALES, |_| Sales::new())
+        .with_projection(STOCK, |_| Stock::new())
+        .with_projection(MENU, |_| Menus::new())
+    }
+
+    // Add a read model next to the tables. It is built from the whole event log
+    // when the service starts and kept up to date from then on.
+    pub fn with_projection<P, F>(mut self, name: &'static str, make: F) -> Self
+    where
+        P: Projection,
+        F: Fn(&Config) -> P + Send + Sync + 'static,
+    {
+        self.projections.push(Box::new(move |config, store, projections| {
+            let source = StoreEventSource::new(store);
+            let projector = Projector::new(make(config), source)
+                .with_page_size(config.projector.page_size);
+            projections.spawn(name, projector);
+        }));
+        self
+    }
+
+    fn open_store(&self) -> Arc<dyn EventStore> {
+        let database = &self.config.database;
+        let store: anyhow::Result<Arc<dyn EventStore>> = match database.backend {
+            Backend::Sqlite => Db::init(&database.path, database).map(|db| Arc::new(db) as _),
+            Backend::Postgres => {
+                let url = database
+                    .url
+                    .as_ref()
+                    .expect("Please set url in [database] to use postgres");
+                PostgresDb::init(url, database).map(|db| Arc::new(db) as _)
+            }
+            Backend::Memory => Ok(Arc::new(MemoryStore::new()) as _),
+        };
+        store.expect("Something went wrong when connecting the database")
+    }
+
+    // Move events stored before `before` into an archive file. See `archive::archive_before`.
+    pub fn archive(&self, before: Time) {
+        info!("Setting up database connection...");
+        let store = self.open_store();
+        let dir = Path::new(&self.config.archive.dir);
+        match archive_before(store, &self.config, before, dir) {
+            Ok(Some(report)) => info!(
+                "Archived {} events up to event {} into {:?}",
+                report.n_events, report.version, report.file
+            ),
+            Ok(None) => info!("Nothing to archive."),
+            Err(e) => error!("Could not archive events: {}", e),
         }
     }
 
     pub async fn serve(&self) {
         info!("Initializing the application...");
         info!("Setting up database connection...");
-        let db = Arc::new(
-            Db::init(&self.db_file).expect("Something went wrong when connecting the database"),
-        );
-        let event_source = SqliteEventSource::new(db.clone());
-        let projector = RestaurantProjector::new(self.config.restaurant.n_table, event_source);
-        let projector = Arc::new(Mutex::new(projector));
+        let store = self.open_store();
+        let event_source = StoreEventSource::new(store.clone());
+        let tables = RestaurantProjector::new(self.config.restaurant.n_table)
+            .with_courses(Arc::new(self.config.restaurant.courses_by_menu()));
+        info!("Putting new menus from the config on the menu...");
+        let mut menus = Projector::new(Menus::new(), event_source.clone())
+            .with_page_size(self.config.projector.page_size);
+        menus
+            .restore_snapshot(EventVersion::MAX)
+            .and_then(|_| menus.update())
+            .expect("There are some bad events in the database.");
+        if let Some(seed) = menus.projection.seed(&self.config.restaurant.starting_menus()) {
+            store
+                .insert_event(&seed, &Metadata::default())
+                .expect("Could not put the menus from the config on the menu.");
+        }
+        let mut projector = Projector::new(tables, event_source)
+            .with_page_size(self.config.projector.page_size)
+            .with_snapshot_interval(self.config.projector.snapshot_interval);
         info!("Catching up old events...");
         projector
-            .lock()
-            .await
+            .restore_snapshot(EventVersion::MAX)
+            .expect("There is a bad snapshot in the database.");
+        projector
             .update()
             .expect("There are some bad events in the database.");
+        let poll_interval = self.config.projector.poll_interval.map(Duration::from_millis);
+        let mut projections = Projections::new(poll_interval);
+        // Everything stored so far has to be in the views before anyone gets to see them
+        projections.notify(projector.projection.version());
+        projections.spawn(TABLES, projector);
+        for make in self.projections.iter() {
+            make(&self.config, store.clone(), &mut projections);
+        }
+        let projections = Arc::new(projections);
+        let store = AsyncStore::new(store, self.config.database.batch_size);
 
         info!("Making a service...");
         let addr = SocketAddr::from_str(&format!(
@@ -61,11 +165,11 @@ impl App {
         .expect("Please use a correct ip and port");
         let make_svc = make_service_fn(move |_conn| {
             let config = self.config.clone();
-            let db = db.clone();
-            let projector = projector.clone();
+            let store = store.clone();
+            let projections = projections.clone();
             async move {
                 Ok::<_, Infallible>(service_fn(move |req| {
-                    restaurant_service(config.clone(), db.clone(), projector.clone(), req)
+                    restaurant_service(config.clone(), store.clone(), projections.clone(), req)
                 }))
             }
         });
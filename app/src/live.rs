@@ -0,0 +1,372 @@
+use std::{any::Any, collections::HashMap, ops::Deref, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use tokio::sync::{watch, Mutex, MutexGuard};
+use tracing::{debug, error};
+
+use crate::{
+    event::EventVersion,
+    projection::{Projection, Projector},
+    projector::EventSource,
+};
+
+// How long to wait before trying again after a failed update. It doubles
+// with every failure in a row, up to `MAX_RETRY`.
+const FIRST_RETRY: Duration = Duration::from_millis(100);
+const MAX_RETRY: Duration = Duration::from_secs(10);
+
+// A view right after event `version`. Never changes once published.
+#[derive(Debug, Default)]
+pub struct Published<V> {
+    pub version: EventVersion,
+    pub view: V,
+    // The latest stored version when the update behind this view started
+    pub target: EventVersion,
+    // Why the projection could not go any further, if it is stuck
+    pub error: Option<String>,
+}
+
+impl<V> Deref for Published<V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        &self.view
+    }
+}
+
+// A projection kept up to date in the background
+pub struct Live<V> {
+    // The latest version known to be stored
+    committed: watch::Receiver<EventVersion>,
+    view: watch::Receiver<Arc<Published<V>>>,
+}
+
+impl<V> Live<V> {
+    // The latest view. It has every event notified before this call.
+    // Fails only if an update started after this call failed too.
+    pub async fn view(&self) -> Result<Arc<Published<V>>> {
+        let target = *self.committed.borrow();
+        let mut view = self.view.clone();
+        let view = view
+            .wait_for(|v| v.version >= target || (v.error.is_some() && v.target >= target))
+            .await?
+            .clone();
+        if view.version < target {
+            bail!(
+                "The projection is stuck at event {}. {}",
+                view.version,
+                view.error.as_deref().unwrap_or_default()
+            );
+        }
+        Ok(view)
+    }
+}
+
+// Every live projection by name. Writers tell it about new events, and readers
+// get the latest views without touching the database or the projections.
+pub struct Projections {
+    committed: watch::Sender<EventVersion>,
+    // With this, projections also catch up on their own every so often.
+    // Only needed when someone else writes into the same database.
+    poll_interval: Option<Duration>,
+    live: HashMap<&'static str, Box<dyn Any + Send + Sync>>,
+    // Held by writes that depend on a view, from reading it until the views know
+    // about the write. See `lock_writes`.
+    checked_writes: Mutex<()>,
+}
+
+impl Projections {
+    pub fn new(poll_interval: Option<Duration>) -> Self {
+        Self {
+            committed: watch::channel(0).0,
+            poll_interval,
+            live: HashMap::new(),
+            checked_writes: Mutex::new(()),
+        }
+    }
+
+    // Keep the guard until the write is stored and notified. Views read meanwhile
+    // can't miss another write that was checked against them, since that one
+    // waits for the guard.
+    pub async fn lock_writes(&self) -> MutexGuard<'_, ()> {
+        self.checked_writes.lock().await
+    }
+
+    // Must be called inside a tokio runtime.
+    // The projection is updated in its own task until the registry is dropped.
+    pub fn spawn<P, S>(&mut self, name: &'static str, projector: Projector<P, S>)
+    where
+        P: Projection,
+        S: EventSource + Send + 'static,
+    {
+        let (view_tx, view) = watch::channel(Arc::new(Published {
+            version: projector.projection.version(),
+            view: projector.projection.view(),
+            target: 0,
+            error: None,
+        }));
+        let committed = self.committed.subscribe();
+        tokio::spawn(run(
+            name,
+            projector,
+            committed.clone(),
+            view_tx,
+            self.poll_interval,
+        ));
+        self.live.insert(name, Box::new(Live { committed, view }));
+    }
+
+    // None if there is no projection `name` with views of type `V`
+    pub fn get<V: Send + Sync + 'static>(&self, name: &str) -> Option<&Live<V>> {
+        self.live.get(name)?.downcast_ref()
+    }
+
+    pub async fn view<V: Send + Sync + 'static>(&self, name: &str) -> Result<Arc<Published<V>>> {
+        self.get(name)
+            .ok_or(anyhow!("No projection called {}", name))?
+            .view()
+            .await
+    }
+
+    // Tell the projections that event `version` has been stored
+    pub fn notify(&self, version: EventVersion) {
+        self.committed.send_if_modified(|committed| {
+            let newer = version > *committed;
+            if newer {
+                *committed = version;
+            }
+            newer
+        });
+    }
+}
+
+async fn run<P, S>(
+    name: &'static str,
+    mut projector: Projector<P, S>,
+    mut committed: watch::Receiver<EventVersion>,
+    view: watch::Sender<Arc<Published<P::View>>>,
+    poll_interval: Option<Duration>,
+) where
+    P: Projection,
+    S: EventSource + Send + 'static,
+{
+    // Start by catching up with whatever is there already
+    let mut polling = true;
+    // Set while updates keep failing, since the cause may go away by itself
+    let mut retry: Option<Duration> = None;
+    loop {
+        let target = *committed.borrow_and_update();
+        if polling || target > projector.projection.version() {
+            // Fetching events is blocking IO
+            let result;
+            (projector, result) = match tokio::task::spawn_blocking(move || {
+                let result = projector.update();
+                (projector, result)
+            })
+            .await
+            {
+                Ok(done) => done,
+                Err(e) => {
+                    error!("The {} projection task died. {}", name, e);
+                    return;
+                }
+            };
+            retry = match &result {
+                Ok(_) => None,
+                Err(e) => {
+                    let delay = retry.map_or(FIRST_RETRY, |d| (d * 2).min(MAX_RETRY));
+                    error!(
+                        "Could not update the {} projection. Trying again in {:?}. {}",
+                        name, delay, e
+                    );
+                    Some(delay)
+                }
+            };
+            let projection = &projector.projection;
+            debug!("Publishing {} at event {}", name, projection.version());
+            view.send_replace(Arc::new(Published {
+                version: projection.version(),
+                view: projection.view(),
+                target,
+                error: result.err().map(|e| e.to_string()),
+            }));
+        }
+        let wait = retry.into_iter().chain(poll_interval).min();
+        polling = tokio::select! {
+            changed = committed.changed() => match changed {
+                Ok(_) => false,
+                // Nobody is listening anymore
+                Err(_) => return,
+            },
+            _ = tokio::time::sleep(wait.unwrap_or_default()), if wait.is_some() => true,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        event::{Command, Event},
+        projector::{RestaurantProjector, Tables},
+        restaurant::TableId,
+    };
+    use std::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    struct SharedSource {
+        events: Arc<Mutex<Vec<Event>>>,
+        // How many fetches to fail before working again
+        failures: Arc<Mutex<usize>>,
+    }
+
+    impl SharedSource {
+        fn push(&self, table_id: TableId) -> EventVersion {
+            let mut events = self.events.lock().unwrap();
+            let version = events.len() as EventVersion + 1;
+            events.push(Event::order(version, Command::New, 0, table_id, &[("a", 1)]));
+            version
+        }
+    }
+
+    impl EventSource for SharedSource {
+        fn fetch(&self, from_version: EventVersion, limit: usize) -> Result<Vec<Event>> {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                bail!("The database is busy");
+            }
+            let events = self.events.lock().unwrap();
+            Ok(events
+                .iter()
+                .filter(|e| e.version >= from_version)
+                .take(limit)
+                .cloned()
+                .collect())
+        }
+    }
+
+    // How many events there are. To have something that isn't the tables.
+    #[derive(Default)]
+    struct Count(EventVersion);
+
+    impl Projection for Count {
+        type View = EventVersion;
+
+        fn version(&self) -> EventVersion {
+            self.0
+        }
+
+        fn handle(&mut self, event: &Event) -> Result<()> {
+            self.0 = event.version;
+            Ok(())
+        }
+
+        fn skip(&mut self, event: &Event) {
+            self.0 = event.version;
+        }
+
+        fn view(&self) -> EventVersion {
+            self.0
+        }
+    }
+
+    fn tables(source: &SharedSource, poll_interval: Option<Duration>) -> Projections {
+        let mut projections = Projections::new(poll_interval);
+        projections.spawn("tables", Projector::new(RestaurantProjector::new(3), source.clone()));
+        projections
+    }
+
+    #[tokio::test]
+    async fn test_view_follows_notifications() {
+        let source = SharedSource::default();
+        let live = tables(&source, None);
+        let view = || live.view::<Tables>("tables");
+        assert_eq!(view().await.unwrap().version, 0);
+
+        live.notify(source.push(1));
+        live.notify(source.push(1));
+        let first = view().await.unwrap();
+        let quantity = |view: &Published<Tables>, table| {
+            view.get_table(table)
+                .unwrap()
+                .items
+                .get("a")
+                .map(|i| i.quantity)
+        };
+        assert_eq!(first.version, 2);
+        assert_eq!(quantity(&first, 1), Some(2));
+
+        // Written behind its back, so it doesn't know yet
+        source.push(2);
+        assert_eq!(view().await.unwrap().version, 2);
+        live.notify(3);
+        assert_eq!(quantity(&view().await.unwrap(), 2), Some(1));
+
+        // Views handed out earlier stay as they were
+        live.notify(source.push(1));
+        let latest = view().await.unwrap();
+        assert_eq!(quantity(&latest, 1), Some(3));
+        assert_eq!(quantity(&first, 1), Some(2));
+        assert_eq!(quantity(&first, 2), None);
+    }
+
+    #[tokio::test]
+    async fn test_view_polls() {
+        let source = SharedSource::default();
+        let live = tables(&source, Some(Duration::from_millis(10)));
+        source.push(0);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(live.view::<Tables>("tables").await.unwrap().version, 1);
+    }
+
+    #[tokio::test]
+    async fn test_stuck_projection() {
+        let source = SharedSource::default();
+        let live = tables(&source, None);
+        // Table 5 doesn't exist, so the projector can't get past it
+        live.notify(source.push(5));
+        let err = live.view::<Tables>("tables").await.unwrap_err();
+        assert!(err.to_string().contains("stuck at event 0"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_recovers_after_failure() {
+        let source = SharedSource::default();
+        let live = tables(&source, None);
+        *source.failures.lock().unwrap() = 1;
+        live.notify(source.push(1));
+        let err = live.view::<Tables>("tables").await.unwrap_err();
+        assert!(err.to_string().contains("busy"), "{}", err);
+
+        // Tries again by itself, without anything new being written
+        tokio::time::sleep(FIRST_RETRY * 3).await;
+        assert_eq!(live.view::<Tables>("tables").await.unwrap().version, 1);
+
+        // An old failure doesn't answer for events written after it
+        *source.failures.lock().unwrap() = 1;
+        live.notify(source.push(1));
+        assert!(live.view::<Tables>("tables").await.is_err());
+        live.notify(source.push(2));
+        let view = live.view::<Tables>("tables").await.unwrap();
+        assert_eq!(view.version, 3);
+    }
+
+    #[tokio::test]
+    async fn test_independent_projections() {
+        let source = SharedSource::default();
+        source.push(0);
+        let mut live = tables(&source, None);
+        live.notify(source.push(5));
+        // Added later and catches up from the start by itself
+        live.spawn("count", Projector::new(Count::default(), source.clone()));
+
+        let count = live.view::<EventVersion>("count").await.unwrap();
+        assert_eq!((count.version, count.view), (2, 2));
+        // The tables got stuck on the bad event, which doesn't bother the count
+        assert!(live.view::<Tables>("tables").await.is_err());
+
+        assert!(live.get::<Tables>("count").is_none());
+        assert!(live.get::<Tables>("nothing").is_none());
+    }
+}
@@ -1,4 +1,4 @@
-use app::config::Config;
+use app::config::{Backend, Config};
 use tracing::info;
 
 #[tokio::main]
@@ -7,7 +7,24 @@ async fn main() {
         .with_env_filter("app=debug")
         .init();
     info!("Loading config file...");
-    let config = Config::from_file("./config/restaurant.toml");
-    let my_app = app::App::new(config, "./events.db".into());
-    my_app.serve().await;
+    let mut config = Config::from_file("./config/restaurant.toml");
+    let mut args: Vec<String> = std::env::args().collect();
+    // app --ephemeral keeps the events in memory only. Handy for demos.
+    if let Some(i) = args.iter().position(|a| a == "--ephemeral") {
+        args.remove(i);
+        info!("Running ephemeral. Nothing will be saved.");
+        config.database.backend = Backend::Memory;
+    }
+    let my_app = app::App::new(config);
+    match args.get(1).map(|a| a.as_str()) {
+        // app archive <unix timestamp>
+        Some("archive") => {
+            let before = args
+                .get(2)
+                .and_then(|t| t.parse().ok())
+                .expect("Please provide a cutoff unix timestamp, e.g. app archive 1666051200");
+            my_app.archive(before);
+        }
+        _ => my_app.serve().await,
+    }
 }
@@ -0,0 +1,156 @@
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use toro::Toro;
+
+use crate::event::{EventVersion, Metadata};
+use crate::projector::Snapshot;
+use crate::restaurant::{TableId, Time};
+use crate::store::{EventFilter, EventRow, EventStore};
+
+// `EventStore` that forgets everything once the app stops. For tests and demos.
+#[derive(Default)]
+pub struct MemoryStore {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    events: Vec<StoredEvent>,
+    snapshots: BTreeMap<EventVersion, Snapshot>,
+    // Survives `compact`, so versions never go backward
+    last_version: EventVersion,
+}
+
+// Columns the SQL stores keep next to the row for filtering
+struct StoredEvent {
+    row: EventRow,
+    command: String,
+    table_id: Option<TableId>,
+}
+
+impl Inner {
+    // Parts the snapshot doesn't have are kept from the one saved before
+    fn save_snapshot(&mut self, snapshot: &Snapshot) {
+        let mut snapshot = snapshot.clone();
+        if let Some(saved) = self.snapshots.remove(&snapshot.version) {
+            snapshot.stock = snapshot.stock.or(saved.stock);
+            snapshot.menus = snapshot.menus.or(saved.menus);
+        }
+        self.last_version = self.last_version.max(snapshot.version);
+        self.snapshots.insert(snapshot.version, snapshot);
+    }
+
+    fn insert(&mut self, toro: &Toro, metadata: &Metadata) -> EventVersion {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("time went backward.")
+            .as_secs();
+        self.last_version += 1;
+        let row = EventRow {
+            version: self.last_version,
+            event_toro: toro.to_toro_string(),
+            timestamp,
+            metadata: metadata.clone(),
+        };
+        self.events.push(StoredEvent {
+            row,
+            command: toro.command.to_string(),
+            table_id: toro.tables.single(),
+        });
+        self.last_version
+    }
+}
+
+impl StoredEvent {
+    fn matches(&self, filter: &EventFilter) -> bool {
+        let metadata = &self.row.metadata;
+        fn same<T: PartialEq>(wanted: &Option<T>, actual: Option<&T>) -> bool {
+            wanted.is_none() || wanted.as_ref() == actual
+        }
+        same(&filter.actor, metadata.actor.as_ref())
+            && same(&filter.device_id, metadata.device_id.as_ref())
+            && same(&filter.correlation_id, metadata.correlation_id.as_ref())
+            && same(&filter.table_id, self.table_id.as_ref())
+            && same(&filter.command, Some(&self.command))
+            && filter.since.is_none_or(|t| self.row.timestamp >= t)
+            && filter.until.is_none_or(|t| self.row.timestamp <= t)
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl EventStore for MemoryStore {
+    fn insert_event(&self, toro: &Toro, metadata: &Metadata) -> Result<EventVersion> {
+        let mut inner = self.inner.lock().unwrap();
+        Ok(inner.insert(toro, metadata))
+    }
+
+    fn insert_events(&self, events: &[(Toro, Metadata)]) -> Result<Vec<EventVersion>> {
+        let mut inner = self.inner.lock().unwrap();
+        Ok(events.iter().map(|(t, m)| inner.insert(t, m)).collect())
+    }
+
+    fn get_events(&self, from_version: EventVersion, limit: usize) -> Result<Vec<EventRow>> {
+        self.query_events(&EventFilter::default(), from_version, Some(limit))
+    }
+
+    fn query_events(
+        &self,
+        filter: &EventFilter,
+        from_version: EventVersion,
+        limit: Option<usize>,
+    ) -> Result<Vec<EventRow>> {
+        let inner = self.inner.lock().unwrap();
+        // Events are kept in version order
+        let start = inner
+            .events
+            .partition_point(|e| e.row.version < from_version);
+        Ok(inner.events[start..]
+            .iter()
+            .filter(|e| e.matches(filter))
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|e| e.row.clone())
+            .collect())
+    }
+
+    fn get_version_at(&self, timestamp: Time) -> Result<Option<EventVersion>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .events
+            .iter()
+            .filter(|e| e.row.timestamp <= timestamp)
+            .map(|e| e.row.version)
+            .max())
+    }
+
+    fn save_snapshot(&self, snapshot: &Snapshot) -> Result<()> {
+        self.inner.lock().unwrap().save_snapshot(snapshot);
+        Ok(())
+    }
+
+    fn load_snapshot(&self, until: EventVersion) -> Result<Option<Snapshot>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .snapshots
+            .range(..=until)
+            .next_back()
+            .map(|(_, s)| s.clone()))
+    }
+
+    fn compact(&self, snapshot: &Snapshot) -> Result<usize> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.save_snapshot(snapshot);
+        let before = inner.events.len();
+        inner.events.retain(|e| e.row.version > snapshot.version);
+        Ok(before - inner.events.len())
+    }
+}
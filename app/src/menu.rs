@@ -0,0 +1,331 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use serde_derive::Serialize;
+use toro::{Command, Param, TableSelector, Toro};
+use tracing::warn;
+
+use crate::{
+    event::{Event, EventVersion, MenuChange},
+    projection::Projection,
+    projector::Snapshot,
+    restaurant::{Aliases, Menu, Price},
+};
+
+// Name of the menu projection among the live projections
+pub const MENU: &str = "menu";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MenuEntry {
+    pub name: Menu,
+    pub price: Option<Price>,
+}
+
+// Everything the restaurant serves, by name
+#[derive(Debug, Clone, Default)]
+pub struct MenuView(pub Arc<BTreeMap<Menu, Option<Price>>>);
+
+impl MenuView {
+    pub fn serves(&self, menu: &str) -> bool {
+        self.0.contains_key(menu)
+    }
+
+    // The name on the menu for whatever the staff typed.
+    // Case doesn't matter and aliases work.
+    pub fn find(&self, name: &str, aliases: &Aliases) -> Option<Menu> {
+        if self.serves(name) {
+            return Some(name.to_string());
+        }
+        let lower = name.to_lowercase();
+        self.0
+            .keys()
+            .find(|m| m.to_lowercase() == lower)
+            .or_else(|| {
+                aliases
+                    .iter()
+                    .find(|(alias, _)| alias.to_lowercase() == lower)
+                    .map(|(_, m)| m)
+                    .filter(|m| self.serves(m))
+            })
+            .cloned()
+    }
+
+    // At most 3 menus that look like what was meant, closest first.
+    // Only a third of the letters may be off.
+    pub fn suggest(&self, name: &str, aliases: &Aliases) -> Vec<Menu> {
+        let lower = name.to_lowercase();
+        let limit = lower.chars().count() / 3;
+        let menus = self.0.keys().map(|m| (m, m));
+        let aliased = aliases.iter().filter(|(_, m)| self.serves(m));
+        let mut close: BTreeMap<&Menu, usize> = BTreeMap::new();
+        for (written, menu) in menus.chain(aliased) {
+            let distance = edit_distance(&lower, &written.to_lowercase());
+            if distance <= limit {
+                let best = close.entry(menu).or_insert(distance);
+                *best = distance.min(*best);
+            }
+        }
+        let mut close: Vec<(&Menu, usize)> = close.into_iter().collect();
+        close.sort_by_key(|(_, distance)| *distance);
+        close.into_iter().take(3).map(|(m, _)| m.clone()).collect()
+    }
+
+    pub fn entries(&self) -> Vec<MenuEntry> {
+        self.0
+            .iter()
+            .map(|(name, price)| MenuEntry {
+                name: name.clone(),
+                price: *price,
+            })
+            .collect()
+    }
+}
+
+// How many letters have to be added, removed, changed or swapped with the next one
+// to turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+// The menu part of a snapshot
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SavedMenus {
+    pub served: BTreeMap<Menu, Option<Price>>,
+    pub gone: BTreeSet<Menu>,
+}
+
+// The menu as changed by the events.
+// The menus in the config get on it with an `add menu` event, see `Menus::seed`.
+#[derive(Default)]
+pub struct Menus {
+    version: EventVersion,
+    menus: Arc<BTreeMap<Menu, Option<Price>>>,
+    // Retired or renamed. The config doesn't bring them back.
+    gone: BTreeSet<Menu>,
+}
+
+impl Menus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // An `add menu` for the menus in the config that have never been on the menu
+    pub fn seed(&self, config_menus: &[Menu]) -> Option<Toro> {
+        let new: BTreeSet<&Menu> = config_menus
+            .iter()
+            .filter(|m| !self.menus.contains_key(*m) && !self.gone.contains(*m))
+            .collect();
+        if new.is_empty() {
+            return None;
+        }
+        Some(Toro {
+            command: Command::AddMenu,
+            tables: TableSelector::None,
+            param: Some(Param::Menu(new.into_iter().cloned().collect())),
+            reason: None,
+            hold: Vec::new(),
+            course: None,
+            sets: Vec::new(),
+        })
+    }
+
+    fn apply(&mut self, change: &MenuChange) {
+        let menus = Arc::make_mut(&mut self.menus);
+        match change {
+            MenuChange::Add(added) => {
+                for menu in added.iter() {
+                    menus.entry(menu.clone()).or_default();
+                    self.gone.remove(menu);
+                }
+            }
+            // Keeps the price. Logs from before `Menus::seed` rename menus from the config
+            // that no event added.
+            MenuChange::Rename(from, to) => {
+                if menus.contains_key(from) || !self.gone.contains(from) {
+                    let price = menus.remove(from).unwrap_or_default();
+                    menus.insert(to.clone(), price);
+                    self.gone.insert(from.clone());
+                    self.gone.remove(to);
+                }
+            }
+            MenuChange::Retire(retired) => {
+                for menu in retired.iter() {
+                    menus.remove(menu);
+                    self.gone.insert(menu.clone());
+                }
+            }
+            MenuChange::Price(prices) => {
+                for (menu, price) in prices.iter() {
+                    if let Some(target) = menus.get_mut(menu) {
+                        *target = Some(*price);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Projection for Menus {
+    type View = MenuView;
+
+    fn version(&self) -> EventVersion {
+        self.version
+    }
+
+    fn handle(&mut self, event: &Event) -> Result<()> {
+        if let Some(change) = &event.menu_change {
+            self.apply(change);
+        }
+        self.version = event.version;
+        Ok(())
+    }
+
+    fn skip(&mut self, event: &Event) {
+        self.version = event.version;
+    }
+
+    fn view(&self) -> MenuView {
+        MenuView(self.menus.clone())
+    }
+
+    // Archives made before menus were in the snapshot don't have them,
+    // so the menu starts over from the config with the next `Menus::seed`
+    fn restore(&mut self, snapshot: &Snapshot) -> Result<bool> {
+        if snapshot.menus.is_none() {
+            warn!("No menus in the snapshot of event {}", snapshot.version);
+        }
+        let saved = snapshot.menus.clone().unwrap_or_default();
+        self.menus = Arc::new(saved.served);
+        self.gone = saved.gone;
+        self.version = snapshot.version;
+        Ok(true)
+    }
+
+    fn snapshot(&self, snapshot: &mut Snapshot) {
+        snapshot.menus = Some(SavedMenus {
+            served: BTreeMap::clone(&self.menus),
+            gone: self.gone.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::Command;
+
+    fn change(version: EventVersion, command: Command, change: MenuChange) -> Event {
+        let mut event = Event::new(version, command, 0);
+        event.menu_change = Some(change);
+        event
+    }
+
+    fn menus(names: &[&str]) -> Menus {
+        let mut menus = Menus::new();
+        menus.apply(&MenuChange::Add(names.iter().map(|m| m.to_string()).collect()));
+        menus
+    }
+
+    #[test]
+    fn test_menu_changes() {
+        let mut menus = menus(&["ramen", "sushi"]);
+        let events = [
+            change(1, Command::AddMenu, MenuChange::Add(vec!["udon".into()])),
+            change(
+                2,
+                Command::SetPrice,
+                MenuChange::Price(vec![("udon".into(), 90), ("pizza".into(), 1)]),
+            ),
+            change(
+                3,
+                Command::RenameMenu,
+                MenuChange::Rename("udon".into(), "kake udon".into()),
+            ),
+            Event::new(4, Command::Yeet, 0),
+            change(
+                5,
+                Command::RetireMenu,
+                MenuChange::Retire(vec!["sushi".into()]),
+            ),
+        ];
+        for event in events.iter() {
+            menus.handle(event).unwrap();
+        }
+        let view = menus.view();
+        assert_eq!(menus.version(), 5);
+        assert_eq!(
+            view.entries(),
+            vec![
+                MenuEntry {
+                    name: "kake udon".into(),
+                    price: Some(90)
+                },
+                MenuEntry {
+                    name: "ramen".into(),
+                    price: None
+                },
+            ]
+        );
+        assert!(!view.serves("pizza"));
+
+        // Renamed and retired menus in the config stay off the menu
+        let config = ["ramen", "udon", "sushi", "soba"].map(String::from);
+        let seed = menus.seed(&config).unwrap();
+        assert_eq!(seed.to_toro_string(), "add menu: soba");
+
+        let mut snapshot = Snapshot::new(9);
+        menus.snapshot(&mut snapshot);
+        let mut restored = Menus::new();
+        assert!(restored.restore(&snapshot).unwrap());
+        assert_eq!(restored.version(), 9);
+        assert_eq!(restored.view().entries(), view.entries());
+        assert_eq!(restored.seed(&config), Some(seed));
+        // An old snapshot without menus starts over from the config
+        assert!(restored.restore(&Snapshot::new(10)).unwrap());
+        assert!(restored.view().entries().is_empty());
+        assert!(restored.seed(&config).is_some());
+    }
+
+    #[test]
+    fn test_find_and_suggest() {
+        let view = menus(&["yakisoba", "miso soup", "sushi"]).view();
+        let aliases: Aliases = [("Miso".to_string(), "miso soup".to_string())]
+            .into_iter()
+            .collect();
+        assert_eq!(view.find("sushi", &aliases).as_deref(), Some("sushi"));
+        assert_eq!(view.find("YakiSoba", &aliases).as_deref(), Some("yakisoba"));
+        assert_eq!(view.find("miso", &aliases).as_deref(), Some("miso soup"));
+        assert_eq!(view.find("yakisobe", &aliases), None);
+
+        assert_eq!(view.suggest("yakisobe", &aliases), vec!["yakisoba"]);
+        assert_eq!(view.suggest("mizo", &aliases), vec!["miso soup"]);
+        // Too far off to be a typo
+        assert!(view.suggest("pizza", &aliases).is_empty());
+        assert!(view.suggest("su", &aliases).is_empty());
+
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("abc", "abc"), 0);
+        assert_eq!(edit_distance("ramne", "ramen"), 1);
+    }
+}
@@ -0,0 +1,309 @@
+use anyhow::{bail, Result};
+use tracing::{instrument, warn};
+
+use crate::{
+    event::{Event, EventVersion},
+    projector::{EventSource, Snapshot, DEFAULT_PAGE_SIZE},
+};
+
+// A read model built from the event log. Each one keeps its own position in the log,
+// so a new one can be added and rebuilt from the start without touching the others.
+pub trait Projection: Send + 'static {
+    // What readers get to see
+    type View: Send + Sync + 'static;
+
+    // The last event handled. 0 before the first one.
+    fn version(&self) -> EventVersion;
+
+    // Events come in version order, each one once, starting right after `version`
+    fn handle(&mut self, event: &Event) -> Result<()>;
+
+    // Move past an event without handling it. See `Projector::update_until`.
+    fn skip(&mut self, event: &Event);
+
+    fn view(&self) -> Self::View;
+
+    // Start over from a snapshot of the tables, for when the events before it
+    // are archived. False if the projection can't be rebuilt from one.
+    fn restore(&mut self, _snapshot: &Snapshot) -> Result<bool> {
+        Ok(false)
+    }
+
+    // Put what `restore` needs into a snapshot taken right after `version`
+    fn snapshot(&self, _snapshot: &mut Snapshot) {}
+}
+
+// Feeds a projection from an event source
+pub struct Projector<P, S> {
+    pub projection: P,
+    source: S,
+    // How many events to hold in memory at once while catching up
+    page_size: usize,
+    // Save a snapshot every this many events. None means never.
+    snapshot_interval: Option<u64>,
+    last_snapshot_version: EventVersion,
+}
+
+impl<P, S> Projector<P, S>
+where
+    P: Projection,
+    S: EventSource,
+{
+    pub fn new(projection: P, source: S) -> Self {
+        Self {
+            projection,
+            source,
+            page_size: DEFAULT_PAGE_SIZE,
+            snapshot_interval: None,
+            last_snapshot_version: 0,
+        }
+    }
+
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    pub fn with_snapshot_interval(mut self, interval: Option<u64>) -> Self {
+        self.snapshot_interval = interval.filter(|i| *i > 0);
+        self
+    }
+
+    pub fn update(&mut self) -> Result<()> {
+        self.update_until(EventVersion::MAX)
+    }
+
+    // Handle events up to and including the event `until`
+    #[instrument(level = "debug", name = "Updating projection", skip(self))]
+    pub fn update_until(&mut self, until: EventVersion) -> Result<()> {
+        while self.projection.version() < until {
+            let from_version = self.projection.version() + 1;
+            let events = self.source.fetch(from_version, self.page_size)?;
+            let n_events = events.len();
+            if n_events == 0 {
+                // Nothing was stored after the archived events yet
+                return self.restore_archived(from_version, until);
+            }
+            for event in events {
+                // Only a snapshot right before `until` or later can fill a gap,
+                // otherwise the events asked for were archived
+                let expected = self.projection.version() + 1;
+                let restored = event.version > expected
+                    && event.version <= until.saturating_add(1)
+                    && self.restore_before(event.version)?;
+                if event.version != expected && !restored {
+                    bail!(
+                        "Expecting event {} but got event {}. Was it archived?",
+                        expected,
+                        event.version
+                    );
+                }
+                if event.version > until {
+                    return self.save_snapshot_if_due();
+                }
+                // Zero or less got in before quantities were checked. Every projection
+                // skips it instead of counting backwards.
+                match event.bad_quantity() {
+                    Some(item) => {
+                        warn!(
+                            "Skipping event {} with {} * {}",
+                            event.version, item.id, item.quantity
+                        );
+                        self.projection.skip(&event);
+                    }
+                    None => self.projection.handle(&event)?,
+                }
+            }
+            if n_events < self.page_size {
+                break;
+            }
+        }
+        self.save_snapshot_if_due()
+    }
+
+    // Skip replaying by jumping to the latest snapshot at or before `until`.
+    // Does nothing if the projection is already past that snapshot.
+    pub fn restore_snapshot(&mut self, until: EventVersion) -> Result<()> {
+        let snapshot = match self.source.load_snapshot(until)? {
+            Some(snapshot) if snapshot.version > self.projection.version() => snapshot,
+            _ => return Ok(()),
+        };
+        if self.projection.restore(&snapshot)? {
+            self.last_snapshot_version = snapshot.version;
+        }
+        Ok(())
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let mut snapshot = Snapshot::new(self.projection.version());
+        self.projection.snapshot(&mut snapshot);
+        snapshot
+    }
+
+    // Jump over archived events using the snapshot taken right before `version`
+    fn restore_before(&mut self, version: EventVersion) -> Result<bool> {
+        match self.source.load_snapshot(version - 1)? {
+            Some(snapshot) if snapshot.version == version - 1 => {
+                let restored = self.projection.restore(&snapshot)?;
+                if restored {
+                    self.last_snapshot_version = snapshot.version;
+                }
+                Ok(restored)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    // A snapshot newer than the last event means everything up to it was archived
+    fn restore_archived(&mut self, from_version: EventVersion, until: EventVersion) -> Result<()> {
+        if let Some(snapshot) = self.source.load_snapshot(EventVersion::MAX)? {
+            if snapshot.version >= from_version {
+                if snapshot.version > until || !self.projection.restore(&snapshot)? {
+                    bail!("Expecting event {} but it was archived", from_version);
+                }
+                self.last_snapshot_version = snapshot.version;
+            }
+        }
+        self.save_snapshot_if_due()
+    }
+
+    fn save_snapshot_if_due(&mut self) -> Result<()> {
+        if let Some(interval) = self.snapshot_interval {
+            let version = self.projection.version();
+            if version >= self.last_snapshot_version + interval {
+                self.source.save_snapshot(&self.snapshot())?;
+                self.last_snapshot_version = version;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{event::Command, restaurant::Table};
+
+    // Counts new orders
+    #[derive(Default)]
+    struct NewOrders {
+        version: EventVersion,
+        count: usize,
+    }
+
+    impl Projection for NewOrders {
+        type View = usize;
+
+        fn version(&self) -> EventVersion {
+            self.version
+        }
+
+        fn handle(&mut self, event: &Event) -> Result<()> {
+            if matches!(event.command, Command::New) {
+                self.count += 1;
+            }
+            self.version = event.version;
+            Ok(())
+        }
+
+        fn skip(&mut self, event: &Event) {
+            self.version = event.version;
+        }
+
+        fn view(&self) -> usize {
+            self.count
+        }
+
+        fn restore(&mut self, snapshot: &Snapshot) -> Result<bool> {
+            self.count = snapshot.tables.len();
+            self.version = snapshot.version;
+            Ok(true)
+        }
+    }
+
+    struct Events(Vec<Event>);
+
+    // Events before 3 are archived into a snapshot
+    struct Archived(Vec<Event>);
+
+    impl EventSource for Archived {
+        fn fetch(&self, from_version: EventVersion, limit: usize) -> Result<Vec<Event>> {
+            Events(self.0.clone()).fetch(from_version, limit)
+        }
+
+        fn load_snapshot(&self, until: EventVersion) -> Result<Option<Snapshot>> {
+            let snapshot = Snapshot {
+                version: 2,
+                tables: vec![Table::new(0)],
+                stock: None,
+                menus: None,
+            };
+            Ok(Some(snapshot).filter(|_| until >= 2))
+        }
+    }
+
+    impl EventSource for Events {
+        fn fetch(&self, from_version: EventVersion, limit: usize) -> Result<Vec<Event>> {
+            Ok(self
+                .0
+                .iter()
+                .filter(|e| e.version >= from_version)
+                .take(limit)
+                .cloned()
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_update() {
+        let events: Vec<Event> = (1..=5)
+            .map(|v| {
+                let command = if v == 3 { Command::Yeet } else { Command::New };
+                Event::new(v, command, 0)
+            })
+            .collect();
+        for page_size in [1, 2, 5, 10] {
+            let mut projector = Projector::new(NewOrders::default(), Events(events.clone()))
+                .with_page_size(page_size);
+            projector.update().unwrap();
+            assert_eq!(projector.projection.version(), 5);
+            assert_eq!(projector.projection.view(), 4);
+        }
+
+        let mut projector = Projector::new(NewOrders::default(), Events(events)).with_page_size(2);
+        projector.update_until(3).unwrap();
+        assert_eq!(projector.projection.version(), 3);
+        assert_eq!(projector.projection.view(), 2);
+    }
+
+    #[test]
+    fn test_gap() {
+        let events = vec![Event::new(2, Command::New, 0)];
+        let mut projector = Projector::new(NewOrders::default(), Events(events));
+        assert!(projector.update().is_err());
+        assert_eq!(projector.projection.version(), 0);
+    }
+
+    #[test]
+    fn test_restore_after_archive() {
+        let events = vec![Event::new(3, Command::New, 0), Event::new(4, Command::New, 0)];
+        let mut projector = Projector::new(NewOrders::default(), Archived(events));
+        projector.update().unwrap();
+        assert_eq!(projector.projection.version(), 4);
+        assert_eq!(projector.projection.view(), 3);
+
+        // Looking back at archived events
+        let events = vec![Event::new(3, Command::New, 0)];
+        let mut projector = Projector::new(NewOrders::default(), Archived(events));
+        assert!(projector.update_until(1).is_err());
+        projector.update_until(2).unwrap();
+        assert_eq!(projector.projection.version(), 2);
+
+        // Nothing stored since the archive
+        let mut projector = Projector::new(NewOrders::default(), Archived(Vec::new()));
+        assert!(projector.update_until(1).is_err());
+        projector.update().unwrap();
+        assert_eq!(projector.projection.version(), 2);
+        assert_eq!(projector.projection.view(), 1);
+    }
+}
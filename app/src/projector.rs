@@ -1,64 +1,123 @@
 use crate::{
     event::{Command, Event, EventVersion, Payload},
-    restaurant::{CookingTimeEstimator, Table, TableId, Item},
+    menu::SavedMenus,
+    projection::Projection,
+    restaurant::{CookingTimeEstimator, Course, Courses, Item, Menu, Quantity, Table, TableId, Time},
 };
 
+use std::{collections::HashMap, sync::Arc};
+
 use anyhow::{bail, Result};
 use tracing::instrument;
 
+pub const DEFAULT_PAGE_SIZE: usize = 1000;
+
+// Name of the `RestaurantProjector` among the live projections
+pub const TABLES: &str = "tables";
+
+// State of all tables right after the event `version` has been projected.
+// Only tables having some items are kept.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub version: EventVersion,
+    pub tables: Vec<Table>,
+    // What is left of every counted menu. None if taken without it, like the
+    // snapshots the tables take on their own. Stores keep the one saved before then.
+    pub stock: Option<HashMap<Menu, Quantity>>,
+    // Likewise for the menu
+    pub menus: Option<SavedMenus>,
+}
+
+impl Snapshot {
+    // Nothing in it yet. Projections fill in their part.
+    pub fn new(version: EventVersion) -> Self {
+        Self {
+            version,
+            tables: Vec::new(),
+            stock: None,
+            menus: None,
+        }
+    }
+}
+
+// Every table as readers see it
+#[derive(Debug, Clone, Default)]
+pub struct Tables(pub Vec<Arc<Table>>);
+
+impl Tables {
+    pub fn get_table(&self, id: TableId) -> Option<&Table> {
+        self.0.get(id).map(Arc::as_ref)
+    }
+}
+
 pub trait EventSource {
-    // Get events starting from `from_version` until the latest event
-    fn fetch(&self, from_version: EventVersion) -> Result<Vec<Event>>;
+    // Get at most `limit` events starting from `from_version` in version order.
+    // Fewer than `limit` events means there is nothing more to fetch for now.
+    fn fetch(&self, from_version: EventVersion, limit: usize) -> Result<Vec<Event>>;
+
+    // The latest snapshot taken at or before version `until`, if the source keeps any.
+    fn load_snapshot(&self, _until: EventVersion) -> Result<Option<Snapshot>> {
+        Ok(None)
+    }
+
+    fn save_snapshot(&self, _snapshot: &Snapshot) -> Result<()> {
+        Ok(())
+    }
 }
 
-pub struct RestaurantProjector<T> {
+// The tables. Fed by a `Projector` like every other projection.
+pub struct RestaurantProjector {
     pub current_version: EventVersion,
-    pub tables: Vec<Table>,
-    source: T,
+    // Shared with the views handed out to readers. A table is only copied
+    // when it changes while someone is still looking at it.
+    pub tables: Vec<Arc<Table>>,
+    courses: Arc<Courses>,
 }
 
-impl<T> RestaurantProjector<T>
-where
-    T: EventSource,
-{
-    pub fn new(n_table: u64, source: T) -> Self {
+impl RestaurantProjector {
+    pub fn new(n_table: u64) -> Self {
         // In this restaurant table number begins with 0!
-        let tables: Vec<Table> = (0..n_table as usize).map(|n| Table::new(n)).collect();
+        let tables = (0..n_table as usize).map(|i| Arc::new(Table::new(i))).collect();
         Self {
             current_version: 0,
             tables,
-            source,
+            courses: Arc::default(),
         }
     }
 
-    #[instrument(level = "debug", name = "Updating projector", skip(self))]
-    pub fn update(&mut self) -> Result<()> {
-        for event in self.source.fetch(self.current_version + 1)? {
-            self.project(event)?;
-            self.current_version += 1;
-        }
-        Ok(())
+    pub fn with_courses(mut self, courses: Arc<Courses>) -> Self {
+        self.courses = courses;
+        self
     }
 
     pub fn get_table(&self, id: TableId) -> Option<&Table> {
-        self.tables.get(id)
+        self.tables.get(id).map(Arc::as_ref)
     }
 
-    fn process_new_cmd(&mut self, payload: Payload) -> Result<()> {
+    // Items of held courses have no cooking time until their course is fired
+    fn process_new_cmd(&mut self, payload: Payload, hold: &[Course]) -> Result<()> {
         if let Some(table) = self.tables.get_mut(payload.table_id) {
+            let table = Arc::make_mut(table);
             for i in payload.items.into_iter() {
-                let new_item = match table.items.get(&i.id) {
+                let held = self.courses.get(&i.id).is_some_and(|c| hold.contains(c));
+                let (new_item, held) = match table.items.get(&i.id) {
                     Some(t) => {
-                        let new_quant = t.quantity + i.quantity;
-                        Item::new(i.id, new_quant, i.timestamp)
+                        let new_quant = t.quantity.saturating_add(i.quantity);
+                        // Joins whatever is already cooking
+                        let held = held && t.cooking_time.is_none();
+                        (Item::new(i.id, new_quant, i.timestamp), held)
                     }
                     None => {
-                        Item::new(i.id, i.quantity, i.timestamp)
+                        (Item::new(i.id, i.quantity, i.timestamp), held)
                     }
                 };
-                let cooking_time =
-                    CookingTimeEstimator::estimate(&new_item, self.current_version);
-                let new_item = new_item.with_cooking_time(cooking_time);
+                let new_item = if held {
+                    new_item
+                } else {
+                    let cooking_time =
+                        CookingTimeEstimator::estimate(&new_item, self.current_version);
+                    new_item.with_cooking_time(cooking_time)
+                };
                 table.items.insert(new_item.id.clone(), new_item);
             }
             return Ok(());
@@ -66,11 +125,34 @@ where
         bail!("Table {} doesn't exist!", payload.table_id)
     }
 
+    // The clock starts for held items of the course
+    fn process_fire(&mut self, table_id: TableId, course: Course, time: Time) -> Result<()> {
+        if let Some(table) = self.tables.get_mut(table_id) {
+            let fired = |item: &Item| {
+                item.cooking_time.is_none() && self.courses.get(&item.id) == Some(&course)
+            };
+            if !table.items.values().any(fired) {
+                return Ok(());
+            }
+            let table = Arc::make_mut(table);
+            for item in table.items.values_mut() {
+                if fired(item) {
+                    item.timestamp = time;
+                    let cooking_time = CookingTimeEstimator::estimate(item, self.current_version);
+                    item.cooking_time = Some(cooking_time);
+                }
+            }
+            return Ok(());
+        }
+        bail!("Table {} doesn't exist!", table_id)
+    }
+
     fn process_cancel(&mut self, payload: Payload) -> Result<()> {
         if let Some(table) = self.tables.get_mut(payload.table_id) {
+            let table = Arc::make_mut(table);
             for item in payload.items.iter() {
                 if let Some(target_item) = table.items.get_mut(&item.id) {
-                    let quantity = target_item.quantity - item.quantity;
+                    let quantity = target_item.quantity.saturating_sub(item.quantity);
                     if quantity <= 0 {
                         table.items.remove(&item.id);
                     } else {
@@ -84,22 +166,28 @@ where
     }
 
     fn process_yeet(&mut self) -> Result<()> {
-        for table in self.tables.iter_mut() {
-            table.reset();
+        for table in self.tables.iter_mut().filter(|t| !t.items.is_empty()) {
+            Arc::make_mut(table).reset();
         }
         Ok(())
     }
 
     #[instrument(level = "debug", name = "Projecting event", skip(self))]
-    fn project(&mut self, event: Event) -> Result<()> {
+    fn project(&mut self, event: &Event) -> Result<()> {
         // In this projector, we only care new order, cancel, and yeet events.
         match event.command {
-            Command::New => match event.payload {
-                Some(payload) => self.process_new_cmd(payload)?,
+            Command::New => match &event.payload {
+                Some(payload) => self.process_new_cmd(payload.clone(), &event.hold)?,
                 None => bail!("No payload available"),
             },
-            Command::Cancel => match event.payload {
-                Some(payload) => self.process_cancel(payload)?,
+            Command::Fire => match (&event.payload, event.course) {
+                (Some(payload), Some(course)) => {
+                    self.process_fire(payload.table_id, course, event.created)?
+                }
+                _ => bail!("Fire needs a table and a course"),
+            },
+            Command::Cancel => match &event.payload {
+                Some(payload) => self.process_cancel(payload.clone())?,
                 None => bail!("No payload available"),
             },
             Command::Yeet => self.process_yeet()?,
@@ -109,18 +197,65 @@ where
     }
 }
 
+impl Projection for RestaurantProjector {
+    type View = Tables;
+
+    fn version(&self) -> EventVersion {
+        self.current_version
+    }
+
+    fn handle(&mut self, event: &Event) -> Result<()> {
+        self.project(event)?;
+        self.current_version = event.version;
+        Ok(())
+    }
+
+    fn skip(&mut self, event: &Event) {
+        self.current_version = event.version;
+    }
+
+    fn view(&self) -> Tables {
+        Tables(self.tables.clone())
+    }
+
+    fn restore(&mut self, snapshot: &Snapshot) -> Result<bool> {
+        self.process_yeet()?;
+        for table in snapshot.tables.iter() {
+            if let Some(target) = self.tables.get_mut(table.id) {
+                *target = Arc::new(table.clone());
+            }
+        }
+        self.current_version = snapshot.version;
+        Ok(true)
+    }
+
+    // Only tables having some items are kept
+    fn snapshot(&self, snapshot: &mut Snapshot) {
+        snapshot.tables = self
+            .tables
+            .iter()
+            .filter(|t| !t.items.is_empty())
+            .map(|t| Table::clone(t))
+            .collect();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     mod test_helper {
+        use std::{cell::RefCell, rc::Rc};
+
         use crate::{
             event::{Command, Payload},
+            projection::Projector,
             restaurant::Item,
         };
 
         use super::*;
         pub(super) struct MyEventSource {
             max_version: EventVersion,
-            events: Vec<Event>,
+            pub(super) events: Vec<Event>,
+            pub(super) snapshots: Rc<RefCell<Vec<Snapshot>>>,
         }
 
         impl MyEventSource {
@@ -144,34 +279,62 @@ mod tests {
                             .with_payload(Payload::new(1, vec![Item::new("b".into(), 2, 1)])),
                         Event::new(8, Command::Yeet, 1),
                     ],
+                    snapshots: Default::default(),
                 }
             }
         }
 
         impl EventSource for MyEventSource {
-            fn fetch(&self, from_version: EventVersion) -> Result<Vec<Event>> {
+            fn fetch(&self, from_version: EventVersion, limit: usize) -> Result<Vec<Event>> {
                 let result = self
                     .events
                     .iter()
                     .filter(|e| e.version >= from_version && e.version <= self.max_version)
+                    .take(limit)
                     .cloned()
                     .collect();
                 Ok(result)
             }
+
+            fn load_snapshot(&self, until: EventVersion) -> Result<Option<Snapshot>> {
+                let snapshots = self.snapshots.borrow();
+                Ok(snapshots.iter().rev().find(|s| s.version <= until).cloned())
+            }
+
+            fn save_snapshot(&self, snapshot: &Snapshot) -> Result<()> {
+                self.snapshots.borrow_mut().push(snapshot.clone());
+                Ok(())
+            }
+        }
+
+        pub(super) fn initialize_projector_to_version(ver: EventVersion) -> RestaurantProjector {
+            let source = MyEventSource::new(ver);
+            let mut projector = Projector::new(RestaurantProjector::new(10), source);
+            projector.update().unwrap();
+            projector.projection
         }
 
-        pub(super) fn initialize_projector_to_version(
+        pub(super) fn initialize_paged_projector_to_version(
             ver: EventVersion,
-        ) -> RestaurantProjector<MyEventSource> {
+            page_size: usize,
+        ) -> RestaurantProjector {
             let source = MyEventSource::new(ver);
-            let mut projector = RestaurantProjector::new(10, source);
+            let mut projector =
+                Projector::new(RestaurantProjector::new(10), source).with_page_size(page_size);
             projector.update().unwrap();
-            projector
+            projector.projection
         }
     }
-    use self::test_helper::initialize_projector_to_version;
+    use self::test_helper::{
+        initialize_paged_projector_to_version, initialize_projector_to_version, MyEventSource,
+    };
 
     use super::*;
+    use crate::{
+        event::{Command, Payload},
+        projection::Projector,
+        restaurant::Quantity,
+    };
 
     #[test]
     fn test_new_order_projection() {
@@ -185,17 +348,137 @@ mod tests {
     #[test]
     fn test_cancel_order_projection() {
         let projector = initialize_projector_to_version(4);
-        assert!(projector.tables[0].items.get("a").is_none());
-        assert!(projector.tables[1].items.get("b").is_some());
+        assert!(!projector.tables[0].items.contains_key("a"));
+        assert!(projector.tables[1].items.contains_key("b"));
         let projector = initialize_projector_to_version(5);
-        assert!(projector.tables[0].items.get("a").is_none());
-        assert!(projector.tables[1].items.get("b").is_none());
+        assert!(!projector.tables[0].items.contains_key("a"));
+        assert!(!projector.tables[1].items.contains_key("b"));
     }
 
     #[test]
     fn test_yeet_projection() {
         let projector = initialize_projector_to_version(8);
-        assert!(projector.tables[0].items.get("a").is_none());
-        assert!(projector.tables[1].items.get("b").is_none());
+        assert!(!projector.tables[0].items.contains_key("a"));
+        assert!(!projector.tables[1].items.contains_key("b"));
+    }
+
+    #[test]
+    fn test_paged_update() {
+        for page_size in [1, 2, 3, 7] {
+            let projector = initialize_paged_projector_to_version(7, page_size);
+            assert_eq!(projector.current_version, 7);
+            assert_eq!(projector.tables[0].items.get("a").unwrap().quantity, 1);
+            assert_eq!(projector.tables[1].items.get("b").unwrap().quantity, 2);
+        }
+    }
+
+    #[test]
+    fn test_update_until() {
+        let mut projector =
+            Projector::new(RestaurantProjector::new(10), MyEventSource::new(8)).with_page_size(2);
+        projector.update_until(3).unwrap();
+        assert_eq!(projector.projection.current_version, 3);
+        assert!(!projector.projection.tables[0].items.contains_key("a"));
+        assert_eq!(projector.projection.tables[1].items.get("b").unwrap().quantity, 2);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let source = MyEventSource::new(8);
+        let snapshots = source.snapshots.clone();
+        let mut projector = Projector::new(RestaurantProjector::new(10), source)
+            .with_snapshot_interval(Some(3));
+        projector.update_until(2).unwrap();
+        assert!(snapshots.borrow().is_empty());
+        projector.update_until(7).unwrap();
+        let snapshot = snapshots.borrow()[0].clone();
+        assert_eq!(snapshot.version, 7);
+        assert_eq!(snapshot.tables.len(), 2);
+
+        // A projector restored from the snapshot doesn't need the older events
+        let source = MyEventSource::new(8);
+        source.snapshots.borrow_mut().push(Snapshot::new(7));
+        let mut projector = Projector::new(RestaurantProjector::new(10), source);
+        projector.restore_snapshot(EventVersion::MAX).unwrap();
+        assert_eq!(projector.projection.current_version, 7);
+        projector.restore_snapshot(5).unwrap();
+        assert_eq!(projector.projection.current_version, 7);
+        projector.update().unwrap();
+        assert_eq!(projector.projection.current_version, 8);
+
+        // Without the snapshot, the missing events can't be made up for
+        let mut source = MyEventSource::new(8);
+        source.events.remove(0);
+        let mut projector = Projector::new(RestaurantProjector::new(10), source);
+        assert!(projector.update().is_err());
+
+        // With it, archived events are jumped over like in any other projection
+        let mut source = MyEventSource::new(8);
+        source.events.drain(..7);
+        source.snapshots.borrow_mut().push(projector.snapshot());
+        source.snapshots.borrow_mut()[0].version = 7;
+        let mut projector = Projector::new(RestaurantProjector::new(10), source);
+        projector.update().unwrap();
+        assert_eq!(projector.projection.current_version, 8);
+    }
+
+    #[test]
+    fn test_hold_and_fire() {
+        let mut source = MyEventSource::new(10);
+        source.events = vec![
+            Event::order(1, Command::New, 1, 0, &[("soup", 1), ("ramen", 2)])
+                .with_hold(vec![Course::Main]),
+            // Ramen joins the held ones, soup is already cooking
+            Event::order(2, Command::New, 2, 0, &[("ramen", 1), ("soup", 1)])
+                .with_hold(vec![Course::Starter, Course::Main]),
+            Event::new(3, Command::Fire, 30)
+                .with_payload(Payload::new(0, vec![]))
+                .with_course(Course::Main),
+        ];
+        let courses: Courses = [("soup", Course::Starter), ("ramen", Course::Main)]
+            .into_iter()
+            .map(|(m, c)| (m.to_string(), c))
+            .collect();
+        let tables = RestaurantProjector::new(10).with_courses(Arc::new(courses));
+        let mut projector = Projector::new(tables, source);
+        projector.update_until(2).unwrap();
+        let items = &projector.projection.tables[0].items;
+        assert!(items["soup"].cooking_time.is_some());
+        assert_eq!(items["soup"].quantity, 2);
+        assert_eq!(items["ramen"].cooking_time, None);
+        assert_eq!(items["ramen"].quantity, 3);
+
+        projector.update().unwrap();
+        let ramen = &projector.projection.tables[0].items["ramen"];
+        assert_eq!(ramen.timestamp, 30);
+        assert!(ramen.cooking_time.is_some());
+    }
+
+    #[test]
+    fn test_skip_bad_quantity() {
+        let mut source = MyEventSource::new(10);
+        source.events.truncate(2);
+        source.events.extend([
+            Event::order(3, Command::New, 1, 0, &[("a", -5)]),
+            Event::order(4, Command::Cancel, 1, 1, &[("b", 0)]),
+            Event::order(5, Command::New, 1, 0, &[("a", 1)]),
+        ]);
+        let mut projector = Projector::new(RestaurantProjector::new(10), source);
+        projector.update().unwrap();
+        assert_eq!(projector.projection.current_version, 5);
+        assert_eq!(projector.projection.tables[0].items["a"].quantity, 2);
+        assert_eq!(projector.projection.tables[1].items["b"].quantity, 2);
+    }
+
+    #[test]
+    fn test_huge_stored_quantity() {
+        let mut source = MyEventSource::new(10);
+        source.events.truncate(1);
+        source.events.push(Event::order(2, Command::New, 1, 0, &[("a", Quantity::MAX)]));
+        let mut projector = Projector::new(RestaurantProjector::new(10), source);
+        projector.update().unwrap();
+        let a = &projector.projection.tables[0].items["a"];
+        assert_eq!(a.quantity, Quantity::MAX);
+        assert!(a.cooking_time.is_some());
     }
 }
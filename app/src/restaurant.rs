@@ -1,4 +1,7 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use anyhow::anyhow;
+use serde_derive::{Deserialize, Serialize};
 
 use crate::event::EventVersion;
 
@@ -8,8 +11,52 @@ pub type Quantity = i64;
 // Time represent a time interval with a unit of seconds
 pub type Time = u64;
 pub type Menu = String;
+// In the smallest unit of the currency
+pub type Price = u64;
+// Which course each menu belongs to. Menus without one are never held.
+pub type Courses = HashMap<Menu, Course>;
+// Set menus and what comes in each. A menu listed twice comes twice.
+pub type Sets = HashMap<Menu, Vec<Menu>>;
+// Other names the staff use for a menu, such as `miso` for `miso soup`
+pub type Aliases = HashMap<String, Menu>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Course {
+    Starter,
+    Main,
+    Dessert,
+    Drink,
+}
+
+impl FromStr for Course {
+    type Err = anyhow::Error;
+
+    // Both "main" and "mains" work
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().trim_end_matches('s') {
+            "starter" => Ok(Course::Starter),
+            "main" => Ok(Course::Main),
+            "dessert" => Ok(Course::Dessert),
+            "drink" => Ok(Course::Drink),
+            c => Err(anyhow!("Unknown course: {}", c)),
+        }
+    }
+}
+
+impl Display for Course {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Course::Starter => "starter",
+            Course::Main => "main",
+            Course::Dessert => "dessert",
+            Course::Drink => "drink",
+        };
+        write!(f, "{}", s)
+    }
+}
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Table {
     pub id: TableId,
     pub items: HashMap<ItemId, Item>,
@@ -28,7 +75,7 @@ impl Table {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Item {
     pub id: ItemId,
     pub quantity: Quantity,
@@ -52,6 +99,44 @@ impl Item {
     }
 }
 
+// How much of every menu each table has ordered and not cancelled yet.
+// For projections that need to know how much of a cancel actually happened.
+#[derive(Debug, Clone, Default)]
+pub struct Outstanding(HashMap<(TableId, Menu), Quantity>);
+
+impl Outstanding {
+    pub fn from_tables<'a>(tables: impl IntoIterator<Item = &'a Table>) -> Self {
+        Self(
+            tables
+                .into_iter()
+                .flat_map(|t| t.items.values().map(|i| ((t.id, i.id.clone()), i.quantity)))
+                .collect(),
+        )
+    }
+
+    pub fn order(&mut self, table_id: TableId, menu: &Menu, quantity: Quantity) {
+        let outstanding = self.0.entry((table_id, menu.clone())).or_default();
+        *outstanding = outstanding.saturating_add(quantity);
+    }
+
+    // How much was actually cancelled. Never more than what the table has.
+    pub fn cancel(&mut self, table_id: TableId, menu: &Menu, quantity: Quantity) -> Quantity {
+        let key = (table_id, menu.clone());
+        let outstanding = self.0.get(&key).copied().unwrap_or_default();
+        let cancelled = quantity.clamp(0, outstanding);
+        if outstanding == cancelled {
+            self.0.remove(&key);
+        } else {
+            self.0.insert(key, outstanding - cancelled);
+        }
+        cancelled
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
 // This serves as an abstract purpose
 // It should provide an accurate cooking time based on the available data.
 // Such as Kitchen workload, orders in queue and stuff.
@@ -60,6 +145,6 @@ impl CookingTimeEstimator {
     pub fn estimate(item: &Item, event_version: EventVersion) -> Time {
         // A very accurate estimation of a restaurant
         (item.id.len() as u64 * 60 + (event_version % 10) * 60)
-            * ((item.quantity + 2) as f32).log(2.71) as u64
+            * (item.quantity.saturating_add(2) as f32).log(2.71) as u64
     }
 }
@@ -0,0 +1,518 @@
+use std::{collections::BTreeMap, fmt::Write, sync::Arc};
+
+use anyhow::{bail, Result};
+use serde_derive::Serialize;
+
+use crate::{
+    event::{Command, Event, EventVersion, Payload},
+    projection::Projection,
+    projector::Snapshot,
+    restaurant::{Menu, Outstanding, Quantity, TableId, Time},
+};
+
+// Name of the sales projection among the live projections
+pub const SALES: &str = "sales";
+
+const HOUR: Time = 60 * 60;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Counts {
+    pub ordered: Quantity,
+    pub cancelled: Quantity,
+}
+
+impl Counts {
+    pub fn net(&self) -> Quantity {
+        self.ordered.saturating_sub(self.cancelled)
+    }
+
+    fn add(&mut self, other: &Counts) {
+        self.ordered = self.ordered.saturating_add(other.ordered);
+        self.cancelled = self.cancelled.saturating_add(other.cancelled);
+    }
+}
+
+// What got cancelled, why and by whom
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct CancelKey {
+    pub reason: Option<String>,
+    pub menu: Menu,
+    // Name of the token that cancelled it
+    pub staff: Option<String>,
+}
+
+// Everything that happened within one hour
+#[derive(Debug, Clone, Default)]
+pub struct HourSales {
+    pub menus: BTreeMap<Menu, Counts>,
+    pub tables: BTreeMap<TableId, Counts>,
+    pub cancellations: BTreeMap<CancelKey, Quantity>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SalesView {
+    // Sales by the hour, keyed by the unix timestamp the hour starts at
+    pub hours: Arc<BTreeMap<Time, Arc<HourSales>>>,
+    // Sales before this went away with the archived events. None if nothing is archived.
+    pub complete_from: Option<Time>,
+}
+
+#[derive(Debug, Default)]
+pub struct Sales {
+    version: EventVersion,
+    hours: Arc<BTreeMap<Time, Arc<HourSales>>>,
+    // So cancelling more than what was ordered doesn't count
+    outstanding: Outstanding,
+    complete_from: Option<Time>,
+}
+
+impl Sales {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn hour(&mut self, time: Time) -> &mut HourSales {
+        let hours = Arc::make_mut(&mut self.hours);
+        Arc::make_mut(hours.entry(time - time % HOUR).or_default())
+    }
+
+    fn process_new_cmd(&mut self, payload: &Payload, time: Time) {
+        for item in payload.items.iter() {
+            self.outstanding
+                .order(payload.table_id, &item.id, item.quantity);
+            let counts = Counts {
+                ordered: item.quantity,
+                cancelled: 0,
+            };
+            self.count(payload.table_id, &item.id, time, counts);
+        }
+    }
+
+    fn process_cancel(&mut self, payload: &Payload, event: &Event) {
+        for item in payload.items.iter() {
+            let cancelled = self
+                .outstanding
+                .cancel(payload.table_id, &item.id, item.quantity);
+            if cancelled == 0 {
+                continue;
+            }
+            let counts = Counts {
+                ordered: 0,
+                cancelled,
+            };
+            self.count(payload.table_id, &item.id, event.created, counts);
+            let key = CancelKey {
+                reason: event.reason.clone(),
+                menu: item.id.clone(),
+                staff: event.metadata.actor.clone(),
+            };
+            *self
+                .hour(event.created)
+                .cancellations
+                .entry(key)
+                .or_default() += cancelled;
+        }
+    }
+
+    fn count(&mut self, table_id: TableId, menu: &Menu, time: Time, counts: Counts) {
+        let hour = self.hour(time);
+        hour.menus.entry(menu.clone()).or_default().add(&counts);
+        hour.tables.entry(table_id).or_default().add(&counts);
+    }
+
+    // The oldest event left after the archive
+    fn note_oldest(&mut self, created: Time) {
+        if self.complete_from == Some(Time::MAX) {
+            self.complete_from = Some(created);
+        }
+    }
+}
+
+impl Projection for Sales {
+    type View = SalesView;
+
+    fn version(&self) -> EventVersion {
+        self.version
+    }
+
+    fn handle(&mut self, event: &Event) -> Result<()> {
+        self.note_oldest(event.created);
+        match (event.command, &event.payload) {
+            (Command::New, Some(payload)) => self.process_new_cmd(payload, event.created),
+            (Command::Cancel, Some(payload)) => self.process_cancel(payload, event),
+            (Command::New | Command::Cancel, None) => bail!("No payload available"),
+            // Clearing the tables is not a cancellation
+            (Command::Yeet, _) => self.outstanding.clear(),
+            _ => (),
+        }
+        self.version = event.version;
+        Ok(())
+    }
+
+    fn skip(&mut self, event: &Event) {
+        self.note_oldest(event.created);
+        self.version = event.version;
+    }
+
+    fn view(&self) -> SalesView {
+        SalesView {
+            hours: self.hours.clone(),
+            complete_from: self.complete_from,
+        }
+    }
+
+    // Sales before the snapshot are gone with the archived events, so reports
+    // can't go back that far. Only what the tables still have is needed to count
+    // later cancels.
+    fn restore(&mut self, snapshot: &Snapshot) -> Result<bool> {
+        self.outstanding = Outstanding::from_tables(&snapshot.tables);
+        self.hours = Arc::default();
+        // Until the next event tells how far back the log goes
+        self.complete_from = Some(Time::MAX);
+        self.version = snapshot.version;
+        Ok(true)
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct MenuSales {
+    pub menu: Menu,
+    #[serde(flatten)]
+    pub counts: Counts,
+    pub net: Quantity,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct HourVolume {
+    // Unix timestamp the hour starts at
+    pub hour: Time,
+    #[serde(flatten)]
+    pub counts: Counts,
+    pub net: Quantity,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct TableSales {
+    pub table_id: TableId,
+    #[serde(flatten)]
+    pub counts: Counts,
+    pub net: Quantity,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SalesReport {
+    pub from: Time,
+    pub to: Time,
+    // Best sellers first
+    pub menus: Vec<MenuSales>,
+    pub hours: Vec<HourVolume>,
+    pub tables: Vec<TableSales>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct Tally {
+    pub name: String,
+    pub quantity: Quantity,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct CancelLine {
+    #[serde(flatten)]
+    pub key: CancelKey,
+    pub quantity: Quantity,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CancellationReport {
+    pub from: Time,
+    pub to: Time,
+    pub total: Quantity,
+    // Each one most cancelled first
+    pub reasons: Vec<Tally>,
+    pub menus: Vec<Tally>,
+    pub staff: Vec<Tally>,
+    // Every reason, menu and staff combination
+    pub lines: Vec<CancelLine>,
+}
+
+// Most first, then by name
+fn tallies(counts: BTreeMap<String, Quantity>) -> Vec<Tally> {
+    let mut tallies: Vec<_> = counts
+        .into_iter()
+        .map(|(name, quantity)| Tally { name, quantity })
+        .collect();
+    tallies.sort_by(|a, b| {
+        b.quantity
+            .cmp(&a.quantity)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    tallies
+}
+
+impl SalesView {
+    // False if a report from `from` would miss archived sales
+    pub fn covers(&self, from: Time) -> bool {
+        self.complete_from.is_none_or(|t| from >= t)
+    }
+
+    // Hours starting within `from..to`. Hours are the smallest unit,
+    // so `from` is effectively rounded down to the hour.
+    fn hours(&self, from: Time, to: Time) -> impl Iterator<Item = (&Time, &Arc<HourSales>)> {
+        let start = from - from % HOUR;
+        self.hours.range(start..to.max(start))
+    }
+
+    pub fn report(&self, from: Time, to: Time) -> SalesReport {
+        let mut menus: BTreeMap<&Menu, Counts> = BTreeMap::new();
+        let mut tables: BTreeMap<TableId, Counts> = BTreeMap::new();
+        let mut hours = Vec::new();
+        for (hour, sales) in self.hours(from, to) {
+            let mut volume = Counts::default();
+            for (menu, counts) in sales.menus.iter() {
+                menus.entry(menu).or_default().add(counts);
+                volume.add(counts);
+            }
+            for (table_id, counts) in sales.tables.iter() {
+                tables.entry(*table_id).or_default().add(counts);
+            }
+            hours.push(HourVolume {
+                hour: *hour,
+                counts: volume,
+                net: volume.net(),
+            });
+        }
+        let mut menus: Vec<_> = menus
+            .into_iter()
+            .map(|(menu, counts)| MenuSales {
+                menu: menu.clone(),
+                counts,
+                net: counts.net(),
+            })
+            .collect();
+        menus.sort_by(|a, b| b.net.cmp(&a.net).then_with(|| a.menu.cmp(&b.menu)));
+        let tables = tables
+            .into_iter()
+            .map(|(table_id, counts)| TableSales {
+                table_id,
+                counts,
+                net: counts.net(),
+            })
+            .collect();
+        SalesReport {
+            from,
+            to,
+            menus,
+            hours,
+            tables,
+        }
+    }
+
+    pub fn cancellations(&self, from: Time, to: Time) -> CancellationReport {
+        let mut lines: BTreeMap<&CancelKey, Quantity> = BTreeMap::new();
+        for (_, sales) in self.hours(from, to) {
+            for (key, quantity) in sales.cancellations.iter() {
+                *lines.entry(key).or_default() += quantity;
+            }
+        }
+        let mut reasons = BTreeMap::new();
+        let mut menus = BTreeMap::new();
+        let mut staff = BTreeMap::new();
+        for (key, quantity) in lines.iter() {
+            let reason = key.reason.as_deref().unwrap_or("unspecified");
+            *reasons.entry(reason.into()).or_default() += quantity;
+            *menus.entry(key.menu.clone()).or_default() += quantity;
+            let who = key.staff.as_deref().unwrap_or("unknown");
+            *staff.entry(who.into()).or_default() += quantity;
+        }
+        CancellationReport {
+            from,
+            to,
+            total: lines.values().sum(),
+            reasons: tallies(reasons),
+            menus: tallies(menus),
+            staff: tallies(staff),
+            lines: lines
+                .into_iter()
+                .map(|(key, quantity)| CancelLine {
+                    key: key.clone(),
+                    quantity,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl CancellationReport {
+    // One row per reason, menu and staff combination
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("reason,menu,staff,quantity\n");
+        for line in self.lines.iter() {
+            let key = &line.key;
+            writeln!(
+                csv,
+                "{},{},{},{}",
+                csv_field(key.reason.as_deref().unwrap_or_default()),
+                csv_field(&key.menu),
+                csv_field(key.staff.as_deref().unwrap_or_default()),
+                line.quantity
+            )
+            .expect("Writing to a String doesn't fail.");
+        }
+        csv
+    }
+}
+
+impl SalesReport {
+    // One row per menu, hour and table, told apart by the first column
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("kind,key,ordered,cancelled,net\n");
+        let mut row = |kind: &str, key: &str, counts: &Counts| {
+            writeln!(
+                csv,
+                "{},{},{},{},{}",
+                kind,
+                csv_field(key),
+                counts.ordered,
+                counts.cancelled,
+                counts.net()
+            )
+            .expect("Writing to a String doesn't fail.");
+        };
+        for m in self.menus.iter() {
+            row("menu", &m.menu, &m.counts);
+        }
+        for h in self.hours.iter() {
+            row("hour", &h.hour.to_string(), &h.counts);
+        }
+        for t in self.tables.iter() {
+            row("table", &t.table_id.to_string(), &t.counts);
+        }
+        csv
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        event::Metadata,
+        restaurant::{Item, Table},
+    };
+
+    fn sales() -> Sales {
+        let mut sales = Sales::new();
+        let events = [
+            Event::order(1, Command::New, 10, 1, &[("ramen", 2), ("sushi", 1)]),
+            Event::order(2, Command::New, 20, 2, &[("ramen", 1)]),
+            // Only 1 sushi to cancel
+            Event::order(3, Command::Cancel, HOUR + 5, 1, &[("sushi", 3)])
+                .with_reason("out of stock"),
+            Event::new(4, Command::Yeet, HOUR + 6),
+            // Nothing left after yeet
+            Event::order(5, Command::Cancel, HOUR + 7, 1, &[("ramen", 1)]),
+            Event::order(6, Command::New, 3 * HOUR, 2, &[("sushi", 4)]),
+            Event::order(7, Command::Cancel, 3 * HOUR, 2, &[("sushi", 1)])
+                .with_reason("out of stock")
+                .with_metadata(Metadata {
+                    actor: Some("chef".into()),
+                    ..Default::default()
+                }),
+            Event::order(8, Command::Cancel, 3 * HOUR, 2, &[("sushi", 1)]),
+            Event::order(9, Command::New, 3 * HOUR, 2, &[("sushi", 2)]),
+        ];
+        for event in events.iter() {
+            sales.handle(event).unwrap();
+        }
+        sales
+    }
+
+    #[test]
+    fn test_report() {
+        let report = sales().view().report(0, Time::MAX);
+        let menus: Vec<_> = report
+            .menus
+            .iter()
+            .map(|m| (m.menu.as_str(), m.counts.ordered, m.counts.cancelled, m.net))
+            .collect();
+        assert_eq!(menus, vec![("sushi", 7, 3, 4), ("ramen", 3, 0, 3)]);
+        let hours: Vec<_> = report.hours.iter().map(|h| (h.hour, h.net)).collect();
+        assert_eq!(hours, vec![(0, 4), (HOUR, -1), (3 * HOUR, 4)]);
+        let tables: Vec<_> = report.tables.iter().map(|t| (t.table_id, t.net)).collect();
+        assert_eq!(tables, vec![(1, 2), (2, 5)]);
+    }
+
+    #[test]
+    fn test_report_range() {
+        let view = sales().view();
+        // Rounded down to the hour
+        let report = view.report(HOUR + 30, 3 * HOUR);
+        assert_eq!(report.hours.len(), 1);
+        assert_eq!(report.menus.len(), 1);
+        assert_eq!(report.menus[0].counts.cancelled, 1);
+        assert!(view.report(10 * HOUR, HOUR).hours.is_empty());
+    }
+
+    #[test]
+    fn test_csv() {
+        let csv = sales().view().report(3 * HOUR, Time::MAX).to_csv();
+        let expected = format!(
+            "kind,key,ordered,cancelled,net\nmenu,sushi,6,2,4\nhour,{},6,2,4\ntable,2,6,2,4\n",
+            3 * HOUR
+        );
+        assert_eq!(csv, expected);
+        assert_eq!(csv_field("a \"b\", c"), "\"a \"\"b\"\", c\"");
+    }
+
+    #[test]
+    fn test_cancellations() {
+        let view = sales().view();
+        let report = view.cancellations(0, Time::MAX);
+        assert_eq!(report.total, 3);
+        let tally = |t: &Vec<Tally>| -> Vec<(String, Quantity)> {
+            t.iter().map(|t| (t.name.clone(), t.quantity)).collect()
+        };
+        assert_eq!(
+            tally(&report.reasons),
+            vec![("out of stock".into(), 2), ("unspecified".into(), 1)]
+        );
+        assert_eq!(tally(&report.menus), vec![("sushi".into(), 3)]);
+        assert_eq!(
+            tally(&report.staff),
+            vec![("unknown".into(), 2), ("chef".into(), 1)]
+        );
+        assert_eq!(report.lines.len(), 3);
+
+        let csv = view.cancellations(3 * HOUR, Time::MAX).to_csv();
+        assert_eq!(
+            csv,
+            "reason,menu,staff,quantity\n,sushi,,1\nout of stock,sushi,chef,1\n"
+        );
+    }
+
+    #[test]
+    fn test_archived() {
+        assert!(sales().view().covers(0));
+        let mut sales = sales();
+        let mut snapshot = Snapshot::new(9);
+        let mut table = Table::new(2);
+        table.items.insert("sushi".into(), Item::new("sushi".into(), 4, 3 * HOUR));
+        snapshot.tables.push(table);
+        sales.restore(&snapshot).unwrap();
+        assert!(!sales.view().covers(5 * HOUR));
+        sales.handle(&Event::order(10, Command::Cancel, 5 * HOUR, 2, &[("sushi", 1)])).unwrap();
+        let view = sales.view();
+        assert!(!view.covers(0));
+        assert!(view.covers(5 * HOUR));
+        let report = view.report(5 * HOUR, Time::MAX);
+        assert_eq!(report.menus[0].counts.cancelled, 1);
+        assert_eq!(report.menus[0].counts.ordered, 0);
+    }
+}
@@ -1,24 +1,34 @@
 use crate::{
-    config::Config,
-    db::Db,
-    projector::RestaurantProjector,
-    restaurant::{Item, Table},
-    sql_source::SqliteEventSource,
+    async_store::AsyncStore,
+    config::{Config, MaxQuantities, Role},
+    store::EventFilter,
+    event::{expand_sets, Event, EventVersion, Metadata},
+    kitchen::{KitchenQueue, QueueItem, KITCHEN},
+    live::Projections,
+    menu::{MenuEntry, MenuView, Menus, MENU},
+    projection::{Projection, Projector},
+    projector::{RestaurantProjector, Tables, TABLES},
+    restaurant::{Aliases, Item, Quantity, Table, Time},
+    sales::{SalesView, SALES},
+    stock::{StockLevels, STOCK},
+    store_source::StoreEventSource,
 };
 use anyhow::{anyhow, bail, Result};
-use hyper::{Body, Request, Response};
+use hyper::{Body, Method, Request, Response};
+use serde_derive::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
     fmt::Display,
     sync::Arc,
     time::{SystemTime, UNIX_EPOCH},
 };
-use tokio::sync::Mutex;
-use toro::{MenuName, Toro};
+use toro::{
+    syntax::{ErrorKind, ParseError, MAX_QUANTITY},
+    Command, Keywords, MenuName, Mode, Param, TableSelector, Toro,
+};
 use tracing::{debug, info, instrument};
 
-type Projector = Arc<Mutex<RestaurantProjector<SqliteEventSource>>>;
-
-const SECRET: &str = "pl3a53-h1r3-m3";
+type Store = AsyncStore;
 
 fn my_response<T>(status: u16, body: T) -> Response<Body>
 where
@@ -30,57 +40,147 @@ where
         .expect("This shouldn't fail.")
 }
 
+fn json_response(status: u16, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(body.into())
+        .expect("This shouldn't fail.")
+}
+
 pub async fn restaurant_service(
     config: Arc<Config>,
-    db: Arc<Db>,
-    projector: Projector,
+    store: Store,
+    projections: Arc<Projections>,
     req: Request<Body>,
 ) -> Result<Response<Body>> {
-    if let Some(api_token) = req.headers().get("Authorization") {
+    let token = match req.headers().get("Authorization") {
         // A very safe and secure non-constant time comparison
-        if api_token != SECRET {
-            return Ok(my_response(401, "You shall not pass."));
+        Some(api_token) => match api_token.to_str().ok().and_then(|t| config.auth.find(t)) {
+            Some(token) => token,
+            None => return Ok(my_response(401, "You shall not pass.")),
+        },
+        None => return Ok(my_response(401, "Did you forget our secret word?")),
+    };
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from)
+    };
+    let metadata = Metadata {
+        actor: Some(token.name.clone()),
+        role: Some(token.role.as_str().into()),
+        device_id: header("X-Device-Id"),
+        correlation_id: header("X-Correlation-Id"),
+    };
+    let role = token.role;
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/events") => return list_events(store, role, req.uri().query()).await,
+        (&Method::GET, "/kitchen/queue") => return get_kitchen_queue(projections).await,
+        (&Method::GET, "/menu") => {
+            return get_menu(config, store, projections, role, req.uri().query()).await
         }
-    } else {
-        return Ok(my_response(401, "Did you forget our secret word?"));
+        (&Method::GET, "/reports/sales") => {
+            return get_report(projections, role, Report::Sales, req.uri().query()).await
+        }
+        (&Method::GET, "/reports/cancellations") => {
+            return get_report(projections, role, Report::Cancellations, req.uri().query()).await
+        }
+        (&Method::GET, path) if path.starts_with("/tables/") => {
+            let table_id = path.trim_start_matches("/tables/");
+            return get_table(config, store, projections, role, table_id, req.uri().query())
+                .await;
+        }
+        (_, "/order") => (),
+        _ => return Ok(my_response(200, "Nothing to see here.")),
     }
-    if req.uri().path() != "/order" {
-        return Ok(my_response(200, "Nothing to see here."));
+    // Clients can ask for sloppy orders to be accepted
+    let mode = match header("X-Toro-Mode").map(|m| m.parse()) {
+        Some(Ok(mode)) => mode,
+        Some(Err(_)) => return Ok(my_response(400, "X-Toro-Mode is strict or lenient.")),
+        None => Mode::Strict,
+    };
+    let locale = header("X-Toro-Locale");
+    if let Some(name) = locale.as_ref().filter(|l| !config.locales.contains_key(*l)) {
+        return Ok(my_response(400, format!("We don't speak {}.", name)));
     }
     let full_body = hyper::body::to_bytes(req.into_body()).await?;
     let payload_str = String::from_utf8(full_body.into_iter().collect())?;
 
-    parse_order_string(config, db, projector, &payload_str).await
+    let order = Order {
+        text: &payload_str,
+        mode,
+        locale,
+    };
+    parse_order_string(config, store, projections, order, role, metadata).await
+}
+
+// An order string as it came in
+struct Order<'a> {
+    text: &'a str,
+    mode: Mode,
+    // Name of the locale in the config. English if none.
+    locale: Option<String>,
 }
 
-#[instrument(name = "Got an order string", skip(config, db, projector))]
+#[instrument(
+    name = "Got an order string",
+    skip(config, store, projections, order, role, metadata),
+    fields(
+        payload_str = order.text,
+        actor = ?metadata.actor,
+        device = ?metadata.device_id,
+        correlation = ?metadata.correlation_id,
+    )
+)]
 async fn parse_order_string(
     config: Arc<Config>,
-    db: Arc<Db>,
-    projector: Projector,
-    payload_str: &str,
+    store: Store,
+    projections: Arc<Projections>,
+    order: Order<'_>,
+    role: Role,
+    metadata: Metadata,
 ) -> Result<Response<Body>> {
-    match Toro::from_toro_string(payload_str) {
-        Ok(toro) => process_order(config, db, projector, toro).await,
+    // Stored in English whatever it was written in
+    let english = Keywords::default();
+    let keywords = match &order.locale {
+        Some(name) => config.locales.get(name).map_or(&english, |l| &l.0),
+        None => &english,
+    };
+    match Toro::parse_with(order.text, order.mode, keywords) {
+        Ok(toro) => process_order(config, store, projections, toro, role, metadata).await,
         Err(e) => {
             info!("It was a weird order string. Error: {}", e);
-            return Ok(my_response(400, "Invalid order string"));
+            match e.downcast_ref::<ParseError>().map(|e| e.kind) {
+                Some(ErrorKind::NotPositive) => {
+                    Ok(my_response(400, "Quantities must be more than zero."))
+                }
+                Some(ErrorKind::TooLarge) => Ok(my_response(
+                    400,
+                    format!("At most {} of a menu at once.", MAX_QUANTITY),
+                )),
+                _ => Ok(my_response(400, "Invalid order string")),
+            }
         }
     }
 }
 
 async fn process_order(
     config: Arc<Config>,
-    db: Arc<Db>,
-    projector: Projector,
+    store: Store,
+    projections: Arc<Projections>,
     toro: Toro,
+    role: Role,
+    metadata: Metadata,
 ) -> Result<Response<Body>> {
     debug!("Successfully parsed the order string.");
 
     use toro::Command::*;
     let result = match toro.command {
-        Check => check_table(config, db, projector, toro).await,
-        _ => store_event(config, db, projector, toro).await,
+        Check => check_table(config, store, projections, toro).await,
+        CheckKitchen => check_kitchen(projections).await,
+        _ => store_event(config, store, projections, toro, role, metadata).await,
     };
     match result {
         Ok(_) => result,
@@ -112,29 +212,57 @@ impl<'a> Display for TableQuery<'a> {
             // Sticking with Option wrapped. Just in case some menu in the query
             // doesn't exist, so we still can handle it.
             Some(menus) => menus.iter().map(|m| table.items.get(m)).collect(),
-            None => table.items.values().map(|i| Some(i)).collect(),
+            None => table.items.values().map(Some).collect(),
         };
-        for i in list.iter() {
-            if let Some(item) = i {
-                write!(f, "{} * {}", item.id, item.quantity)?;
-                // Actually this should be fetched from the event `check` itself
-                // So it will show the same result every time.
-                // But with the current design, we don't store the event `check`,
-                // so we will just go with this.
-                let now = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .expect("time went backward.")
-                    .as_secs();
-                let elapsed = now - item.timestamp;
-                let cooking_time = item.cooking_time.ok_or(std::fmt::Error)?;
-                let eta = cooking_time.saturating_sub(elapsed);
-                if eta == 0 {
-                    writeln!(f, " finished")?;
-                } else {
-                    let (eta_min, eta_sec) = (eta / 60, eta % 60);
-                    writeln!(f, " in {} minutes {} seconds", eta_min, eta_sec)?;
+        for item in list.iter().flatten() {
+            write!(f, "{} * {}", item.id, item.quantity)?;
+            // Actually this should be fetched from the event `check` itself
+            // So it will show the same result every time.
+            // But with the current design, we don't store the event `check`,
+            // so we will just go with this.
+            let cooking_time = match item.cooking_time {
+                Some(t) => t,
+                None => {
+                    writeln!(f, " on hold")?;
+                    continue;
                 }
-            }
+            };
+            let elapsed = now() - item.timestamp;
+            write_eta(f, cooking_time.saturating_sub(elapsed))?;
+        }
+        Ok(())
+    }
+}
+
+fn now() -> Time {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backward.")
+        .as_secs()
+}
+
+fn write_eta(f: &mut std::fmt::Formatter<'_>, eta: Time) -> std::fmt::Result {
+    if eta == 0 {
+        writeln!(f, " finished")
+    } else {
+        let (eta_min, eta_sec) = (eta / 60, eta % 60);
+        writeln!(f, " in {} minutes {} seconds", eta_min, eta_sec)
+    }
+}
+
+struct KitchenList<'a>(&'a KitchenQueue);
+
+impl<'a> Display for KitchenList<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Kitchen:")?;
+        if self.0.is_empty() {
+            write!(f, "Nothing to cook.")?;
+            return Ok(());
+        }
+        let now = now();
+        for item in self.0.iter() {
+            write!(f, "Table {}: {} * {}", item.table_id, item.menu, item.quantity)?;
+            write_eta(f, item.ready_at().saturating_sub(now))?;
         }
         Ok(())
     }
@@ -142,39 +270,105 @@ impl<'a> Display for TableQuery<'a> {
 
 #[instrument(name = "Checking table", skip_all)]
 async fn check_table(
-    _config: Arc<Config>,
-    _db: Arc<Db>,
-    projector: Projector,
+    config: Arc<Config>,
+    _store: Store,
+    projections: Arc<Projections>,
     toro: Toro,
 ) -> Result<Response<Body>> {
-    let mut proj = projector.lock().await;
-    // Just In Time™ update
-    proj.update()?;
-    let table = proj
-        .get_table(toro.table_id.ok_or(anyhow!("Expecting table id"))?)
-        .ok_or(anyhow!("Table not found."))?;
+    // Already up to date with every order stored so far
+    let view = projections.view::<Tables>(TABLES).await?;
+    let mut tables = Vec::new();
+    for id in toro.tables.ids(config.restaurant.n_table as usize) {
+        let table = view.get_table(id).ok_or(anyhow!("Table not found."))?;
+        // Nobody wants to read through every empty table
+        if toro.tables != TableSelector::All || !table.items.is_empty() {
+            tables.push(table);
+        }
+    }
     let query = match toro.param {
         Some(p) => match p {
-            toro::Param::Menu(v) => Some(v),
+            toro::Param::Menu(v) => {
+                // Names nobody serves are left as they are and just not found
+                let menu = projections.view::<MenuView>(MENU).await?;
+                let aliases = &config.restaurant.aliases;
+                Some(v.into_iter().map(|m| menu.find(&m, aliases).unwrap_or(m)).collect())
+            }
             _ => bail!("Only menu parameter is supported"),
         },
         None => None,
     };
-    Ok(my_response(
-        200,
-        format!("{}", TableQuery::from_table(table, query)),
-    ))
+    let answer = match tables.as_slice() {
+        [] => "No order yet.".to_string(),
+        [table] => format!("{}", TableQuery::from_table(table, query)),
+        tables => {
+            let answers: Vec<String> = tables
+                .iter()
+                .map(|t| TableQuery::from_table(t, query.clone()).to_string())
+                .map(|answer| answer.trim_end().to_string())
+                .collect();
+            answers.join("\n\n")
+        }
+    };
+    Ok(my_response(200, answer))
+}
+
+#[instrument(name = "Checking the kitchen", skip_all)]
+async fn check_kitchen(projections: Arc<Projections>) -> Result<Response<Body>> {
+    let view = projections.view::<KitchenQueue>(KITCHEN).await?;
+    Ok(my_response(200, format!("{}", KitchenList(&view))))
+}
+
+// Why the order asks for too much, if it does. Sets count as themselves.
+// A sum too large to add up is more than any limit.
+fn over_max(max: &MaxQuantities, ordered: &[(MenuName, Quantity)]) -> Option<String> {
+    let too_many = || Some(format!("Too many in one order. At most {}.", max.per_order));
+    let mut wanted: BTreeMap<&MenuName, Quantity> = BTreeMap::new();
+    for (menu, quantity) in ordered.iter() {
+        let sum = wanted.entry(menu).or_default();
+        *sum = match sum.checked_add(*quantity) {
+            Some(sum) => sum,
+            None => return too_many(),
+        };
+    }
+    for (menu, quantity) in wanted.iter() {
+        match max.per_menu.get(*menu) {
+            Some(most) if quantity > most => {
+                return Some(format!("Too many {}. At most {} in one order.", menu, most))
+            }
+            _ => (),
+        }
+    }
+    match wanted.values().try_fold(0, |total: Quantity, q| total.checked_add(*q)) {
+        Some(total) if total <= max.per_order => None,
+        _ => too_many(),
+    }
+}
+
+fn not_served(menu: &MenuView, aliases: &Aliases, wrong_menus: &[MenuName]) -> String {
+    let mut suggestions: Vec<MenuName> = Vec::new();
+    for m in wrong_menus.iter().flat_map(|name| menu.suggest(name, aliases)) {
+        if !suggestions.contains(&m) {
+            suggestions.push(m);
+        }
+    }
+    if suggestions.is_empty() {
+        format!("We don't serve {:?}", wrong_menus)
+    } else {
+        format!("We don't serve {:?}. Did you mean {:?}?", wrong_menus, suggestions)
+    }
 }
 
 #[instrument(name = "Storing event", skip_all)]
 async fn store_event(
     config: Arc<Config>,
-    db: Arc<Db>,
-    _projector: Projector,
-    toro: Toro,
+    store: Store,
+    projections: Arc<Projections>,
+    mut toro: Toro,
+    role: Role,
+    metadata: Metadata,
 ) -> Result<Response<Body>> {
     let max_table = config.restaurant.n_table;
-    if let Some(table_id) = toro.table_id {
+    if let Some(table_id) = toro.tables.last() {
         if table_id >= max_table as usize {
             debug!(
                 "Bad event: Too large table id {}. Maximum is {}",
@@ -189,33 +383,114 @@ async fn store_event(
             return Ok(my_response(400, err_str));
         }
     }
-    if let Some(param) = &toro.param {
-        let wrong_menus: Vec<&MenuName> = match param {
-            toro::Param::MenuQuantities(v) => v
-                .iter()
-                .filter(|(m, _)| !config.restaurant.menus.contains(m))
-                .map(|(m, _)| m)
-                .collect(),
-            toro::Param::Menu(v) => v
-                .iter()
-                .filter(|&m| !config.restaurant.menus.contains(m))
-                .collect(),
+    if toro.command.edits_menu() && !role.is_manager() {
+        return Ok(my_response(403, "Only managers can change the menu."));
+    }
+    if !toro.sets.is_empty() {
+        return Ok(my_response(400, "Order sets by their names. We fill in the rest."));
+    }
+    if let Some(param) = &mut toro.param {
+        use toro::Param::*;
+        let aliases = &config.restaurant.aliases;
+        // Menus that must be on the menu already, and new names that must not be
+        let (served, new): (Vec<&mut MenuName>, Vec<&MenuName>) = match (toro.command, param) {
+            (Command::AddMenu, Menu(v)) => (Vec::new(), v.iter().collect()),
+            (_, Rename(from, to)) => (vec![from], vec![&*to]),
+            (_, MenuQuantities(v)) => (v.iter_mut().map(|(m, _)| m).collect(), Vec::new()),
+            (_, Menu(v)) => (v.iter_mut().collect(), Vec::new()),
+            (_, Prices(v)) => (v.iter_mut().map(|(m, _)| m).collect(), Vec::new()),
         };
+        let menu = projections.view::<MenuView>(MENU).await?;
+        // What gets stored is the name on the menu
+        let mut wrong_menus: Vec<MenuName> = Vec::new();
+        for name in served.into_iter() {
+            match menu.find(name, aliases) {
+                Some(found) => *name = found,
+                None => wrong_menus.push(name.clone()),
+            }
+        }
         if !wrong_menus.is_empty() {
             debug!(
-                "Got menu name that is not supported in the config: {:?}",
+                "Got menu name that is not on the menu: {:?}",
                 wrong_menus
             );
-            return Ok(my_response(
-                400,
-                format!("We don't serve {:?}", wrong_menus),
-            ));
+            return Ok(my_response(400, not_served(&menu, aliases, &wrong_menus)));
+        }
+        let taken: Vec<&MenuName> =
+            new.into_iter().filter(|m| menu.find(m, aliases).is_some()).collect();
+        if !taken.is_empty() {
+            return Ok(my_response(400, format!("We already serve {:?}", taken)));
+        }
+    }
+    if let (Command::New, Some(Param::MenuQuantities(ordered))) = (toro.command, &toro.param) {
+        if let Some(too_many) = over_max(&config.restaurant.max_quantities, ordered) {
+            debug!("Ordered too many: {}", too_many);
+            return Ok(my_response(400, too_many));
         }
     }
-    match db.insert_event(toro) {
-        Ok(_) => {
+    let toro = expand_sets(toro, &config.restaurant.sets);
+    // One event for each table, so every event is still about one table
+    let toros: Vec<Toro> = match toro.tables {
+        TableSelector::Range(..) | TableSelector::All => toro
+            .tables
+            .ids(max_table as usize)
+            .map(|t| Toro {
+                tables: TableSelector::One(t),
+                ..toro.clone()
+            })
+            .collect(),
+        TableSelector::None | TableSelector::One(_) => vec![toro],
+    };
+    // Whatever gets stored must make an event later on, e.g. no unknown courses
+    let event = match toros.first().map(|t| Event::from_toro(t, 0, 0)) {
+        Some(Ok(event)) => event,
+        Some(Err(e)) => {
+            debug!("Bad event: {}", e);
+            return Ok(my_response(400, format!("{}", e)));
+        }
+        None => return Ok(my_response(400, "We don't have any table.")),
+    };
+    // Nothing is taken from the stock until the event is projected,
+    // so two orders racing for the last ones must go one after the other
+    let _checked = match event.command {
+        Command::New => Some(projections.lock_writes().await),
+        _ => None,
+    };
+    if let (Command::New, Some(payload)) = (event.command, &event.payload) {
+        // Sets take from the stock of their components
+        let stock = projections.view::<StockLevels>(STOCK).await?;
+        let mut wanted: BTreeMap<&MenuName, i64> = BTreeMap::new();
+        for item in payload.items.iter() {
+            let sum = wanted.entry(&item.id).or_default();
+            *sum = match sum.checked_add(item.quantity) {
+                Some(sum) => sum,
+                None => return Ok(my_response(400, "Too many in one order.")),
+            };
+        }
+        let short: Vec<String> = wanted
+            .into_iter()
+            .filter_map(|(m, q)| match stock.remaining(m) {
+                Some(left) if left <= 0 => Some(format!("{} is sold out.", m)),
+                Some(left) if left < q => Some(format!("Only {} {} left.", left, m)),
+                _ => None,
+            })
+            .collect();
+        if !short.is_empty() {
+            debug!("Not enough stock: {:?}", short);
+            return Ok(my_response(400, format!("Sorry. {}", short.join(" "))));
+        }
+    }
+    let events = toros.into_iter().map(|t| (t, metadata.clone())).collect();
+    match store.insert_events(events).await {
+        Ok(versions) => {
             info!("The event looks nice. Putting it in the DB.");
-            Ok(my_response(200, "Order received"))
+            if let Some(version) = versions.last() {
+                projections.notify(*version);
+            }
+            match versions.len() {
+                1 => Ok(my_response(200, "Order received")),
+                n => Ok(my_response(200, format!("Order received for {} tables", n))),
+            }
         }
         Err(e) => {
             debug!("Something went wrong with error {}", e);
@@ -223,3 +498,293 @@ async fn store_event(
         }
     }
 }
+
+#[derive(Debug, Serialize)]
+struct QueueLine<'a> {
+    #[serde(flatten)]
+    item: &'a QueueItem,
+    // Seconds until it should be ready. 0 once it is.
+    eta: Time,
+}
+
+#[derive(Debug, Serialize)]
+struct QueueResponse<'a> {
+    version: EventVersion,
+    items: Vec<QueueLine<'a>>,
+}
+
+#[instrument(name = "Listing the kitchen queue", skip_all)]
+async fn get_kitchen_queue(projections: Arc<Projections>) -> Result<Response<Body>> {
+    let view = projections.view::<KitchenQueue>(KITCHEN).await?;
+    let now = now();
+    let body = QueueResponse {
+        version: view.version,
+        items: view
+            .iter()
+            .map(|item| QueueLine {
+                item,
+                eta: item.ready_at().saturating_sub(now),
+            })
+            .collect(),
+    };
+    Ok(json_response(200, serde_json::to_string(&body)?))
+}
+
+#[derive(Debug)]
+enum Report {
+    Sales,
+    Cancellations,
+}
+
+// Query string of GET /reports/*. Timestamps are in unix seconds.
+#[derive(Debug, Deserialize)]
+struct ReportQuery {
+    from: Option<Time>,
+    to: Option<Time>,
+    // json or csv
+    format: Option<String>,
+}
+
+#[instrument(name = "Making a report", skip(projections))]
+async fn get_report(
+    projections: Arc<Projections>,
+    role: Role,
+    report: Report,
+    query: Option<&str>,
+) -> Result<Response<Body>> {
+    if !role.is_manager() {
+        return Ok(my_response(403, "Only managers can see the reports."));
+    }
+    let query: ReportQuery = match serde_urlencoded::from_str(query.unwrap_or_default()) {
+        Ok(q) => q,
+        Err(e) => {
+            info!("Bad report query. Error: {}", e);
+            return Ok(my_response(400, "Invalid query"));
+        }
+    };
+    let csv = match query.format.as_deref() {
+        None | Some("json") => false,
+        Some("csv") => true,
+        Some(_) => return Ok(my_response(400, "Use format=json or format=csv.")),
+    };
+    let view = projections.view::<SalesView>(SALES).await?;
+    let (from, to) = (query.from.unwrap_or(0), query.to.unwrap_or(Time::MAX));
+    if !view.covers(from) {
+        return Ok(my_response(410, "That part of history has been archived."));
+    }
+    let body = match (report, csv) {
+        (Report::Sales, false) => serde_json::to_string(&view.report(from, to))?,
+        (Report::Sales, true) => view.report(from, to).to_csv(),
+        (Report::Cancellations, false) => serde_json::to_string(&view.cancellations(from, to))?,
+        (Report::Cancellations, true) => view.cancellations(from, to).to_csv(),
+    };
+    if csv {
+        return Ok(Response::builder()
+            .status(200)
+            .header("Content-Type", "text/csv")
+            .body(body.into())
+            .expect("This shouldn't fail."));
+    }
+    Ok(json_response(200, body))
+}
+
+const DEFAULT_EVENT_PAGE_SIZE: usize = 100;
+const MAX_EVENT_PAGE_SIZE: usize = 1000;
+
+// Query string of GET /events. Timestamps are in unix seconds.
+#[derive(Debug, Deserialize)]
+struct EventsQuery {
+    from_version: Option<u64>,
+    limit: Option<usize>,
+    table: Option<usize>,
+    command: Option<String>,
+    since: Option<u64>,
+    until: Option<u64>,
+    // Who sent it, from which device, and as part of what
+    actor: Option<String>,
+    device_id: Option<String>,
+    correlation_id: Option<String>,
+}
+
+#[instrument(name = "Listing events", skip(store))]
+async fn list_events(store: Store, role: Role, query: Option<&str>) -> Result<Response<Body>> {
+    if !role.is_manager() {
+        return Ok(my_response(403, "Only managers can read the event log."));
+    }
+    let query: EventsQuery = match serde_urlencoded::from_str(query.unwrap_or_default()) {
+        Ok(q) => q,
+        Err(e) => {
+            info!("Bad event log query. Error: {}", e);
+            return Ok(my_response(400, "Invalid query"));
+        }
+    };
+    let command = match query.command.map(|c| c.parse::<Command>()).transpose() {
+        Ok(c) => c.map(|c| c.to_string()),
+        Err(e) => {
+            info!("Bad event log query. Error: {}", e);
+            return Ok(my_response(400, "Invalid query"));
+        }
+    };
+    let filter = EventFilter {
+        actor: query.actor,
+        device_id: query.device_id,
+        correlation_id: query.correlation_id,
+        table_id: query.table,
+        command,
+        since: query.since,
+        until: query.until,
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_EVENT_PAGE_SIZE)
+        .clamp(1, MAX_EVENT_PAGE_SIZE);
+    match store
+        .get_event_page(filter, query.from_version.unwrap_or(1), limit)
+        .await
+    {
+        Ok(page) => Ok(json_response(200, serde_json::to_string(&page)?)),
+        Err(e) => {
+            debug!("Something went wrong with error {}", e);
+            Ok(my_response(500, "Something went wrong inside."))
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct MenuAsOf {
+    version: EventVersion,
+    menus: Vec<MenuEntry>,
+}
+
+// Anyone can see the menu. Only managers can see how it used to be.
+#[instrument(name = "Looking at the menu", skip(config, store, projections))]
+async fn get_menu(
+    config: Arc<Config>,
+    store: Store,
+    projections: Arc<Projections>,
+    role: Role,
+    query: Option<&str>,
+) -> Result<Response<Body>> {
+    let query = match serde_urlencoded::from_str::<AsOfQuery>(query.unwrap_or_default()) {
+        Ok(q) => q,
+        Err(_) => return Ok(my_response(400, "Invalid query")),
+    };
+    let version = match (query.as_of_version, query.as_of) {
+        (None, None) => {
+            let view = projections.view::<MenuView>(MENU).await?;
+            let body = MenuAsOf {
+                version: view.version,
+                menus: view.entries(),
+            };
+            return Ok(json_response(200, serde_json::to_string(&body)?));
+        }
+        _ if !role.is_manager() => {
+            return Ok(my_response(403, "Only managers can look into the past."))
+        }
+        (Some(_), Some(_)) => {
+            return Ok(my_response(400, "Use either as_of_version or as_of. Not both."))
+        }
+        (Some(v), None) => v,
+        (None, Some(t)) => store.get_version_at(t).await?.unwrap_or(0),
+    };
+    // Replayed on the side like the tables
+    let source = StoreEventSource::new(store.blocking());
+    let mut projector = Projector::new(Menus::new(), source)
+        .with_page_size(config.projector.page_size);
+    let replay = tokio::task::spawn_blocking(move || {
+        projector
+            .restore_snapshot(version)
+            .and_then(|_| projector.update_until(version))
+            .map(|_| projector)
+    });
+    let projector = match replay.await? {
+        Ok(projector) => projector,
+        Err(e) => {
+            info!("Could not replay up to event {}. Error: {}", version, e);
+            return Ok(my_response(410, "That part of history has been archived."));
+        }
+    };
+    let body = MenuAsOf {
+        version: projector.projection.version(),
+        menus: projector.projection.view().entries(),
+    };
+    Ok(json_response(200, serde_json::to_string(&body)?))
+}
+
+// Query string of GET /tables/{id}. Use at most one of them.
+// Without both, the table as it is right now is returned.
+#[derive(Debug, Deserialize)]
+struct AsOfQuery {
+    as_of_version: Option<EventVersion>,
+    // Unix timestamp in seconds
+    as_of: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct TableAsOf<'a> {
+    version: EventVersion,
+    table: &'a Table,
+}
+
+#[instrument(name = "Looking at a table", skip(config, store, projections))]
+async fn get_table(
+    config: Arc<Config>,
+    store: Store,
+    projections: Arc<Projections>,
+    role: Role,
+    table_id: &str,
+    query: Option<&str>,
+) -> Result<Response<Body>> {
+    if !role.is_manager() {
+        return Ok(my_response(403, "Only managers can look into the past."));
+    }
+    let (table_id, query) = match (
+        table_id.parse::<usize>(),
+        serde_urlencoded::from_str::<AsOfQuery>(query.unwrap_or_default()),
+    ) {
+        (Ok(t), Ok(q)) => (t, q),
+        _ => return Ok(my_response(400, "Invalid query")),
+    };
+    if table_id >= config.restaurant.n_table as usize {
+        return Ok(my_response(404, "Table not found."));
+    }
+    let version = match (query.as_of_version, query.as_of) {
+        (Some(_), Some(_)) => {
+            return Ok(my_response(400, "Use either as_of_version or as_of. Not both."))
+        }
+        (Some(v), None) => v,
+        (None, Some(t)) => store.get_version_at(t).await?.unwrap_or(0),
+        (None, None) => {
+            let view = projections.view::<Tables>(TABLES).await?;
+            let table = view.get_table(table_id).ok_or(anyhow!("Table not found."))?;
+            let body = TableAsOf {
+                version: view.version,
+                table,
+            };
+            return Ok(json_response(200, serde_json::to_string(&body)?));
+        }
+    };
+    // A throwaway projector, so the live one doesn't have to travel back in time
+    let source = StoreEventSource::new(store.blocking());
+    let tables = RestaurantProjector::new(config.restaurant.n_table)
+        .with_courses(Arc::new(config.restaurant.courses_by_menu()));
+    let mut proj = Projector::new(tables, source).with_page_size(config.projector.page_size);
+    let replay = tokio::task::spawn_blocking(move || {
+        proj.restore_snapshot(version)
+            .and_then(|_| proj.update_until(version))
+            .map(|_| proj)
+    });
+    let proj = match replay.await? {
+        Ok(proj) => proj,
+        Err(e) => {
+            info!("Could not replay up to event {}. Error: {}", version, e);
+            return Ok(my_response(410, "That part of history has been archived."));
+        }
+    };
+    let table = proj.projection.get_table(table_id).ok_or(anyhow!("Table not found."))?;
+    let body = TableAsOf {
+        version: proj.projection.current_version,
+        table,
+    };
+    Ok(json_response(200, serde_json::to_string(&body)?))
+}
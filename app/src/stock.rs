@@ -0,0 +1,185 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{bail, Result};
+use tracing::warn;
+
+use crate::{
+    event::{Command, Event, EventVersion, MenuChange, Payload},
+    projection::Projection,
+    projector::Snapshot,
+    restaurant::{Menu, Outstanding, Quantity},
+};
+
+// Name of the stock projection among the live projections
+pub const STOCK: &str = "stock";
+
+// What is left of every menu that has ever been restocked or sold out
+#[derive(Debug, Clone, Default)]
+pub struct StockLevels(pub Arc<HashMap<Menu, Quantity>>);
+
+impl StockLevels {
+    // None means nobody keeps count of this menu, so there is always enough
+    pub fn remaining(&self, menu: &str) -> Option<Quantity> {
+        self.0.get(menu).copied()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Stock {
+    version: EventVersion,
+    levels: Arc<HashMap<Menu, Quantity>>,
+    // Only what was actually on a table comes back when it is cancelled
+    outstanding: Outstanding,
+}
+
+impl Stock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn process_new_cmd(&mut self, payload: &Payload) {
+        for item in payload.items.iter() {
+            self.outstanding
+                .order(payload.table_id, &item.id, item.quantity);
+            self.adjust(&item.id, -item.quantity);
+        }
+    }
+
+    fn process_cancel(&mut self, payload: &Payload) {
+        for item in payload.items.iter() {
+            let cancelled = self
+                .outstanding
+                .cancel(payload.table_id, &item.id, item.quantity);
+            self.adjust(&item.id, cancelled);
+        }
+    }
+
+    // Menus nobody keeps count of are left alone. Readers may still hold the
+    // levels, so they are only copied when a counted menu changes.
+    fn adjust(&mut self, menu: &Menu, by: Quantity) {
+        if by != 0 && self.levels.contains_key(menu) {
+            if let Some(level) = Arc::make_mut(&mut self.levels).get_mut(menu) {
+                *level = level.saturating_add(by);
+            }
+        }
+    }
+}
+
+impl Projection for Stock {
+    type View = StockLevels;
+
+    fn version(&self) -> EventVersion {
+        self.version
+    }
+
+    fn handle(&mut self, event: &Event) -> Result<()> {
+        match (event.command, &event.payload) {
+            (Command::New, Some(payload)) => self.process_new_cmd(payload),
+            (Command::Cancel, Some(payload)) => self.process_cancel(payload),
+            (Command::New | Command::Cancel, None) => bail!("No payload available"),
+            (Command::Restock, _) => {
+                let levels = Arc::make_mut(&mut self.levels);
+                for item in event.stock.iter() {
+                    let level = levels.entry(item.id.clone()).or_default();
+                    *level = level.saturating_add(item.quantity);
+                }
+            }
+            (Command::SoldOut, _) => {
+                let levels = Arc::make_mut(&mut self.levels);
+                for item in event.stock.iter() {
+                    levels.insert(item.id.clone(), 0);
+                }
+            }
+            // The count goes along with the new name
+            (Command::RenameMenu, _) => {
+                if let Some(MenuChange::Rename(from, to)) = &event.menu_change {
+                    if self.levels.contains_key(from) {
+                        let levels = Arc::make_mut(&mut self.levels);
+                        if let Some(level) = levels.remove(from) {
+                            levels.insert(to.clone(), level);
+                        }
+                    }
+                }
+            }
+            // Whatever was on the tables got eaten
+            (Command::Yeet, _) => self.outstanding.clear(),
+            _ => (),
+        }
+        self.version = event.version;
+        Ok(())
+    }
+
+    fn skip(&mut self, event: &Event) {
+        self.version = event.version;
+    }
+
+    fn view(&self) -> StockLevels {
+        StockLevels(self.levels.clone())
+    }
+
+    // Archives made before stock levels were in the snapshot don't have them,
+    // so nobody keeps count of any menu until the next restock
+    fn restore(&mut self, snapshot: &Snapshot) -> Result<bool> {
+        if snapshot.stock.is_none() {
+            warn!("No stock levels in the snapshot of event {}", snapshot.version);
+        }
+        self.levels = Arc::new(snapshot.stock.clone().unwrap_or_default());
+        self.outstanding = Outstanding::from_tables(&snapshot.tables);
+        self.version = snapshot.version;
+        Ok(true)
+    }
+
+    fn snapshot(&self, snapshot: &mut Snapshot) {
+        snapshot.stock = Some(HashMap::clone(&self.levels));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::restaurant::Item;
+
+    fn items(items: &[(&str, Quantity)]) -> Vec<Item> {
+        items
+            .iter()
+            .map(|(m, q)| Item::new(m.to_string(), *q, 0))
+            .collect()
+    }
+
+    #[test]
+    fn test_stock_levels() {
+        let mut stock = Stock::new();
+        let events = [
+            Event::new(1, Command::Restock, 0).with_stock(items(&[("sushi", 5)])),
+            Event::order(2, Command::New, 0, 1, &[("sushi", 3), ("ramen", 2)]),
+            // Only 3 were on the table
+            Event::order(3, Command::Cancel, 0, 1, &[("sushi", 4)]),
+            Event::order(4, Command::New, 0, 2, &[("sushi", 2)]),
+            Event::new(5, Command::Yeet, 0),
+            // Nothing to give back after yeet
+            Event::order(6, Command::Cancel, 0, 2, &[("sushi", 2)]),
+            Event::new(7, Command::Restock, 0).with_stock(items(&[("sushi", 1)])),
+        ];
+        for event in events.iter() {
+            stock.handle(event).unwrap();
+        }
+        assert_eq!(stock.view().remaining("sushi"), Some(4));
+        assert_eq!(stock.view().remaining("ramen"), None);
+
+        stock
+            .handle(
+                &Event::new(8, Command::SoldOut, 0)
+                    .with_stock(items(&[("sushi", 0), ("ramen", 0)])),
+            )
+            .unwrap();
+        assert_eq!(stock.view().remaining("sushi"), Some(0));
+        assert_eq!(stock.view().remaining("ramen"), Some(0));
+        assert_eq!(stock.version(), 8);
+
+        let mut rename = Event::new(9, Command::RenameMenu, 0);
+        rename.menu_change = Some(MenuChange::Rename("sushi".into(), "nigiri".into()));
+        stock.handle(&rename).unwrap();
+        assert_eq!(stock.view().remaining("sushi"), None);
+        assert_eq!(stock.view().remaining("nigiri"), Some(0));
+    }
+}
@@ -0,0 +1,94 @@
+use anyhow::Result;
+use serde_derive::Serialize;
+use toro::Toro;
+
+use crate::event::{EventVersion, Metadata};
+use crate::projector::Snapshot;
+use crate::restaurant::{TableId, Time};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EventRow {
+    pub version: u64,
+    pub event_toro: String,
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub metadata: Metadata,
+}
+
+// Used for audit queries. `None` means "don't care".
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    pub actor: Option<String>,
+    pub device_id: Option<String>,
+    pub correlation_id: Option<String>,
+    pub table_id: Option<TableId>,
+    // TORO command keyword such as "new order"
+    pub command: Option<String>,
+    // Inclusive range of event timestamps
+    pub since: Option<Time>,
+    pub until: Option<Time>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EventPage {
+    pub events: Vec<EventRow>,
+    // Pass this as `from_version` to get the next page. None if this is the last page.
+    pub next_cursor: Option<EventVersion>,
+}
+
+// Where the events live. Versions start from 1, are handed out by the store,
+// and never go backward, even after `compact`.
+pub trait EventStore: Send + Sync {
+    // Returns the version the event got
+    fn insert_event(&self, toro: &Toro, metadata: &Metadata) -> Result<EventVersion>;
+
+    // All or nothing. Returns the versions in the same order as `events`.
+    fn insert_events(&self, events: &[(Toro, Metadata)]) -> Result<Vec<EventVersion>>;
+
+    // At most `limit` events starting from `from_version`
+    fn get_events(&self, from_version: EventVersion, limit: usize) -> Result<Vec<EventRow>>;
+
+    // Events matching `filter` starting from `from_version`. `None` means no limit.
+    fn query_events(
+        &self,
+        filter: &EventFilter,
+        from_version: EventVersion,
+        limit: Option<usize>,
+    ) -> Result<Vec<EventRow>>;
+
+    // The latest event stored at or before `timestamp`
+    fn get_version_at(&self, timestamp: Time) -> Result<Option<EventVersion>>;
+
+    fn save_snapshot(&self, snapshot: &Snapshot) -> Result<()>;
+
+    // The latest snapshot taken at or before version `until`
+    fn load_snapshot(&self, until: EventVersion) -> Result<Option<Snapshot>>;
+
+    // Save the snapshot and drop every event it covers, all at once.
+    // Returns the number of dropped events.
+    fn compact(&self, snapshot: &Snapshot) -> Result<usize>;
+
+    // Who did what. Mostly for managers going through the log.
+    fn find_events(&self, filter: &EventFilter) -> Result<Vec<EventRow>> {
+        self.query_events(filter, 0, None)
+    }
+
+    fn get_event_page(
+        &self,
+        filter: &EventFilter,
+        from_version: EventVersion,
+        limit: usize,
+    ) -> Result<EventPage> {
+        // Ask for one more row to find out whether there is a next page
+        let mut events = self.query_events(filter, from_version, Some(limit + 1))?;
+        let next_cursor = if events.len() > limit {
+            events.pop().map(|e| e.version)
+        } else {
+            None
+        };
+        Ok(EventPage {
+            events,
+            next_cursor,
+        })
+    }
+}
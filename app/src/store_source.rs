@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use toro::Toro;
+
+use crate::{
+    event::Event,
+    event::EventVersion,
+    projector::{EventSource, Snapshot},
+    store::EventStore,
+};
+
+// Feeds the projector from whichever `EventStore` is in use
+#[derive(Clone)]
+pub struct StoreEventSource {
+    store: Arc<dyn EventStore>,
+}
+
+impl StoreEventSource {
+    pub fn new(store: Arc<dyn EventStore>) -> Self {
+        Self { store }
+    }
+}
+
+impl EventSource for StoreEventSource {
+    fn fetch(&self, from_version: EventVersion, limit: usize) -> Result<Vec<Event>> {
+        self.store
+            .get_events(from_version, limit)?
+            .into_iter()
+            .map(|r| {
+                let toro = Toro::from_stored_string(&r.event_toro)?;
+                Event::from_toro(&toro, r.version, r.timestamp)
+                    .map(|e| e.with_metadata(r.metadata))
+            })
+            .collect()
+    }
+
+    fn load_snapshot(&self, until: EventVersion) -> Result<Option<Snapshot>> {
+        self.store.load_snapshot(until)
+    }
+
+    fn save_snapshot(&self, snapshot: &Snapshot) -> Result<()> {
+        self.store.save_snapshot(snapshot)
+    }
+}
@@ -0,0 +1,123 @@
+// A throwaway PostgreSQL server for the tests.
+//
+// Set RESTAURANT_TEST_POSTGRES_URL to use an existing server instead.
+// Otherwise `initdb` and `postgres` have to be in PATH. They refuse to run as root.
+// Every store gets a database of its own, dropped along with the server.
+use std::{
+    cell::RefCell,
+    net::TcpListener,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use app::{config::DatabaseConfig, postgres_db::PostgresDb};
+use r2d2_postgres::postgres::{Client, NoTls};
+
+pub struct PgServer {
+    url: String,
+    // Only when we started the server ourselves
+    process: Option<(Child, PathBuf)>,
+    databases: RefCell<Vec<String>>,
+}
+
+// Tells the databases of tests running at the same time apart
+static DATABASES: AtomicUsize = AtomicUsize::new(0);
+
+impl PgServer {
+    // Fails the test if there is no way to get a postgres server
+    pub fn start() -> Self {
+        Self::try_start().expect(
+            "No postgres server. Set RESTAURANT_TEST_POSTGRES_URL or put initdb and postgres \
+            in PATH as a user other than root.",
+        )
+    }
+
+    fn try_start() -> Option<Self> {
+        if let Ok(url) = std::env::var("RESTAURANT_TEST_POSTGRES_URL") {
+            return Some(Self {
+                url,
+                process: None,
+                databases: RefCell::default(),
+            });
+        }
+        let dir = std::env::temp_dir().join(format!("restaurant_pg_{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        let initdb = Command::new("initdb")
+            .args(["-U", "postgres", "-A", "trust", "-D"])
+            .arg(&dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        if !matches!(initdb, Ok(status) if status.success()) {
+            std::fs::remove_dir_all(&dir).ok();
+            return None;
+        }
+        let port = TcpListener::bind("127.0.0.1:0")
+            .ok()?
+            .local_addr()
+            .ok()?
+            .port();
+        let child = Command::new("postgres")
+            .arg("-D")
+            .arg(&dir)
+            .args(["-p", &port.to_string(), "-c", "listen_addresses=127.0.0.1"])
+            .arg("-k")
+            .arg(&dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let server = Self {
+            url: format!("postgres://postgres@127.0.0.1:{}/postgres", port),
+            process: Some((child, dir)),
+            databases: RefCell::default(),
+        };
+        let started = Instant::now();
+        while Client::connect(&server.url, NoTls).is_err() {
+            if started.elapsed() > Duration::from_secs(30) {
+                return None;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        Some(server)
+    }
+
+    // URL of a new empty database
+    pub fn fresh_database(&self) -> String {
+        let name = format!(
+            "restaurant_test_{}_{}",
+            std::process::id(),
+            DATABASES.fetch_add(1, Ordering::SeqCst)
+        );
+        let mut client = Client::connect(&self.url, NoTls).unwrap();
+        // One at a time, since neither can run inside a transaction
+        client.batch_execute(&format!("DROP DATABASE IF EXISTS {};", name)).unwrap();
+        client.batch_execute(&format!("CREATE DATABASE {};", name)).unwrap();
+        self.databases.borrow_mut().push(name.clone());
+        let (server, _) = self.url.rsplit_once('/').expect("URL with a database");
+        format!("{}/{}", server, name)
+    }
+
+    // A store with nothing in it
+    pub fn fresh_store(&self) -> PostgresDb {
+        PostgresDb::init(&self.fresh_database(), &DatabaseConfig::default()).unwrap()
+    }
+}
+
+impl Drop for PgServer {
+    fn drop(&mut self) {
+        if let Ok(mut client) = Client::connect(&self.url, NoTls) {
+            for name in self.databases.borrow().iter() {
+                let drop = format!("DROP DATABASE IF EXISTS {} WITH (FORCE);", name);
+                client.batch_execute(&drop).ok();
+            }
+        }
+        if let Some((child, dir)) = self.process.as_mut() {
+            child.kill().ok();
+            child.wait().ok();
+            std::fs::remove_dir_all(dir).ok();
+        }
+    }
+}
@@ -0,0 +1,198 @@
+// Behaviour every `EventStore` has to follow. Each case expects an empty store.
+use app::{
+    event::{EventVersion, Metadata},
+    menu::SavedMenus,
+    projector::Snapshot,
+    restaurant::{Item, Table},
+    store::{EventFilter, EventStore},
+};
+use toro::Toro;
+
+pub type Case = (&'static str, fn(&dyn EventStore));
+
+pub const CASES: [Case; 6] = [
+    ("metadata_roundtrip", metadata_roundtrip),
+    ("insert_batch", insert_batch),
+    ("event_page", event_page),
+    ("version_at", version_at),
+    ("snapshot_roundtrip", snapshot_roundtrip),
+    ("compact", compact),
+];
+
+fn insert(store: &dyn EventStore, order: &str) {
+    let toro = Toro::from_toro_string(order).unwrap();
+    store.insert_event(&toro, &Metadata::default()).unwrap();
+}
+
+fn metadata_roundtrip(store: &dyn EventStore) {
+    let waiter = Metadata {
+        actor: Some("somchai".into()),
+        role: Some("waiter".into()),
+        device_id: Some("tablet-3".into()),
+        correlation_id: Some("abc".into()),
+    };
+    let toro = Toro::from_toro_string("new order for table 1: ramen * 1").unwrap();
+    assert_eq!(store.insert_event(&toro, &waiter).unwrap(), 1);
+    insert(store, "cancel for table 1: ramen * 1");
+
+    let rows = store.get_events(1, 10).unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].metadata, waiter);
+    assert_eq!(rows[1].metadata, Metadata::default());
+    assert_eq!(store.get_events(2, 10).unwrap().len(), 1);
+    assert_eq!(store.get_events(1, 1).unwrap().len(), 1);
+
+    let filter = EventFilter {
+        device_id: Some("tablet-3".into()),
+        ..Default::default()
+    };
+    let rows = store.find_events(&filter).unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].version, 1);
+    assert_eq!(store.find_events(&EventFilter::default()).unwrap().len(), 2);
+}
+
+fn insert_batch(store: &dyn EventStore) {
+    insert(store, "new order for table 1: a * 1");
+    let batch: Vec<_> = (2..5)
+        .map(|table| {
+            let order = format!("new order for table {}: a * 1", table);
+            let metadata = Metadata {
+                actor: Some(format!("waiter {}", table)),
+                ..Default::default()
+            };
+            (Toro::from_toro_string(&order).unwrap(), metadata)
+        })
+        .collect();
+    assert_eq!(store.insert_events(&batch).unwrap(), vec![2, 3, 4]);
+
+    let rows = store.get_events(1, 10).unwrap();
+    assert_eq!(rows.len(), 4);
+    assert_eq!(rows[2].event_toro, "new order for table 3: a * 1");
+    assert_eq!(rows[2].metadata.actor.as_deref(), Some("waiter 3"));
+    assert!(store.insert_events(&[]).unwrap().is_empty());
+}
+
+fn event_page(store: &dyn EventStore) {
+    for i in 0..5 {
+        insert(store, &format!("new order for table {}: a * 1", i % 2));
+    }
+    insert(store, "cancel for table 0: a * 1");
+
+    let all = EventFilter::default();
+    let page = store.get_event_page(&all, 0, 4).unwrap();
+    assert_eq!(page.events.len(), 4);
+    assert_eq!(page.next_cursor, Some(5));
+    let page = store.get_event_page(&all, 5, 4).unwrap();
+    assert_eq!(page.events.len(), 2);
+    assert_eq!(page.next_cursor, None);
+
+    let table_0 = EventFilter {
+        table_id: Some(0),
+        ..Default::default()
+    };
+    let versions: Vec<_> = store
+        .find_events(&table_0)
+        .unwrap()
+        .iter()
+        .map(|e| e.version)
+        .collect();
+    assert_eq!(versions, vec![1, 3, 5, 6]);
+
+    let cancel_table_0 = EventFilter {
+        command: Some("cancel".into()),
+        ..table_0
+    };
+    let page = store.get_event_page(&cancel_table_0, 0, 10).unwrap();
+    assert_eq!(page.events.len(), 1);
+    assert_eq!(page.events[0].version, 6);
+
+    let until_epoch = EventFilter {
+        until: Some(0),
+        ..Default::default()
+    };
+    assert!(store.find_events(&until_epoch).unwrap().is_empty());
+}
+
+fn version_at(store: &dyn EventStore) {
+    assert_eq!(store.get_version_at(u64::MAX).unwrap(), None);
+    insert(store, "new order for table 1: a * 1");
+    insert(store, "yeet");
+    assert_eq!(store.get_version_at(0).unwrap(), None);
+    assert_eq!(store.get_version_at(u64::MAX).unwrap(), Some(2));
+}
+
+fn snapshot_roundtrip(store: &dyn EventStore) {
+    assert!(store.load_snapshot(EventVersion::MAX).unwrap().is_none());
+    let mut table = Table::new(3);
+    table
+        .items
+        .insert("ramen".into(), Item::new("ramen".into(), 2, 100).with_cooking_time(60));
+    store
+        .save_snapshot(&Snapshot {
+            version: 5,
+            tables: vec![table],
+            stock: Some([("ramen".to_string(), 4), ("gyoza".to_string(), 0)].into()),
+            menus: Some(SavedMenus {
+                served: [("ramen".to_string(), Some(900)), ("gyoza".to_string(), None)].into(),
+                gone: ["udon".to_string()].into(),
+            }),
+        })
+        .unwrap();
+    store
+        .save_snapshot(&Snapshot {
+            version: 9,
+            tables: vec![],
+            stock: None,
+            menus: None,
+        })
+        .unwrap();
+
+    let snapshot = store.load_snapshot(8).unwrap().unwrap();
+    assert_eq!(snapshot.version, 5);
+    assert_eq!(snapshot.tables.len(), 1);
+    let item = snapshot.tables[0].items.get("ramen").unwrap();
+    assert_eq!(snapshot.tables[0].id, 3);
+    assert_eq!((item.quantity, item.cooking_time, item.timestamp), (2, Some(60), 100));
+    let stock = snapshot.stock.unwrap();
+    assert_eq!((stock.len(), stock["ramen"], stock["gyoza"]), (2, 4, 0));
+    let menus = snapshot.menus.unwrap();
+    assert_eq!(menus.served.len(), 2);
+    assert_eq!((menus.served["ramen"], menus.served["gyoza"]), (Some(900), None));
+    assert_eq!(menus.gone.into_iter().collect::<Vec<_>>(), vec!["udon"]);
+    let snapshot = store.load_snapshot(EventVersion::MAX).unwrap().unwrap();
+    assert_eq!(snapshot.version, 9);
+    assert!(snapshot.tables.is_empty());
+    assert!(snapshot.stock.is_none());
+    assert!(snapshot.menus.is_none());
+    assert!(store.load_snapshot(4).unwrap().is_none());
+
+    // Saving the tables alone again keeps the stock levels and menus saved with them
+    store.save_snapshot(&Snapshot::new(5)).unwrap();
+    let snapshot = store.load_snapshot(8).unwrap().unwrap();
+    assert!(snapshot.tables.is_empty());
+    assert_eq!(snapshot.stock.unwrap()["ramen"], 4);
+    assert_eq!(snapshot.menus.unwrap().served.len(), 2);
+}
+
+fn compact(store: &dyn EventStore) {
+    for _ in 0..3 {
+        insert(store, "new order for table 1: ramen * 1");
+    }
+    let snapshot = Snapshot {
+        version: 3,
+        tables: vec![],
+        stock: None,
+        menus: None,
+    };
+    assert_eq!(store.compact(&snapshot).unwrap(), 3);
+    assert!(store.get_events(1, 10).unwrap().is_empty());
+    assert_eq!(store.load_snapshot(EventVersion::MAX).unwrap().unwrap().version, 3);
+
+    // New events keep counting from where the compacted ones stopped
+    let toro = Toro::from_toro_string("yeet").unwrap();
+    assert_eq!(store.insert_event(&toro, &Metadata::default()).unwrap(), 4);
+    let rows = store.get_events(1, 10).unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].version, 4);
+}
@@ -0,0 +1,128 @@
+use app::{
+    config::Config,
+    db::Db,
+    event::Metadata,
+    restaurant::Time,
+    store::EventStore,
+    App,
+};
+use client::RestaurantClient;
+use hyper::{Body, Client, Request};
+use toro::Toro;
+
+use anyhow::Result;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_sold_out_after_archive() -> Result<()> {
+    let pid = std::process::id();
+    let db_file = std::env::temp_dir().join(format!("restaurant_sold_out_{}.db", pid));
+    let dir = std::env::temp_dir().join(format!("restaurant_sold_out_archive_{}", pid));
+    std::fs::remove_file(&db_file).ok();
+    let config = Config::from_toml_string(&format!(
+        r###"
+[restaurant]
+table = 10
+menus = ["ramen", "tea"]
+
+[network]
+ip = "0.0.0.0"
+port = 3005
+
+[database]
+path = "{}"
+
+[archive]
+dir = "{}"
+"###,
+        db_file.display(),
+        dir.display()
+    ));
+    let db = Db::init(&db_file, &config.database)?;
+    let events = [
+        "restock: ramen * 2, tea * 5",
+        "new order for table 1: tea * 1",
+        "sold out: ramen",
+    ];
+    for event in events {
+        db.insert_event(&Toro::from_toro_string(event)?, &Metadata::default())?;
+    }
+    drop(db);
+
+    let service = App::new(config);
+    service.archive(Time::MAX);
+    tokio::spawn(async move { service.serve().await });
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let client = RestaurantClient::new("http://localhost:3005/order".into());
+    let sold_out = client.request("new order for table 2: ramen * 1").await?;
+    let too_many = client.request("new order for table 2: tea * 5").await?;
+    std::fs::remove_file(&db_file).ok();
+    std::fs::remove_dir_all(&dir).ok();
+    assert_eq!(sold_out, "Sorry. ramen is sold out.");
+    assert_eq!(too_many, "Sorry. Only 4 tea left.");
+    Ok(())
+}
+
+async fn get_menu(query: &str) -> Result<(u16, String)> {
+    let req = Request::get(format!("http://localhost:3006/menu{}", query))
+        .header("Authorization", "pl3a53-h1r3-m3")
+        .body(Body::empty())?;
+    let res = Client::new().request(req).await?;
+    let status = res.status().as_u16();
+    let full_body = hyper::body::to_bytes(res.into_body()).await?;
+    Ok((status, String::from_utf8(full_body.into_iter().collect())?))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_menu_after_archive() -> Result<()> {
+    let pid = std::process::id();
+    let db_file = std::env::temp_dir().join(format!("restaurant_menu_{}.db", pid));
+    let dir = std::env::temp_dir().join(format!("restaurant_menu_archive_{}", pid));
+    std::fs::remove_file(&db_file).ok();
+    let config = Config::from_toml_string(&format!(
+        r###"
+[restaurant]
+table = 10
+menus = ["ramen", "tea"]
+
+[network]
+ip = "0.0.0.0"
+port = 3006
+
+[database]
+path = "{}"
+
+[archive]
+dir = "{}"
+
+[[auth.tokens]]
+token = "pl3a53-h1r3-m3"
+name = "staff"
+role = "admin"
+"###,
+        db_file.display(),
+        dir.display()
+    ));
+    let db = Db::init(&db_file, &config.database)?;
+    for event in ["add menu: udon", "set price: udon at 90", "retire menu: tea"] {
+        db.insert_event(&Toro::from_toro_string(event)?, &Metadata::default())?;
+    }
+    drop(db);
+
+    let service = App::new(config);
+    service.archive(Time::MAX);
+    tokio::spawn(async move { service.serve().await });
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let now = get_menu("").await?;
+    let archived = get_menu("?as_of_version=2").await?;
+    let after_seed = get_menu("?as_of_version=4").await?;
+    std::fs::remove_file(&db_file).ok();
+    std::fs::remove_dir_all(&dir).ok();
+    // Ramen came from the config after the archive. Tea was retired before it.
+    let menus = r#""menus":[{"name":"ramen","price":null},{"name":"udon","price":90}]"#;
+    assert_eq!(now, (200, format!(r#"{{"version":4,{}}}"#, menus)));
+    assert_eq!(archived.0, 410);
+    assert_eq!(after_seed, now);
+    Ok(())
+}
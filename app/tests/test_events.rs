@@ -0,0 +1,320 @@
+use app::{config::Config, App};
+use client::RestaurantClient;
+use hyper::{Body, Client, Request};
+
+use anyhow::Result;
+
+fn setup_service() -> App {
+    let config = Config::from_toml_string(
+        r###"
+[restaurant]
+table = 10
+menus = ["a", "b"]
+
+[restaurant.courses]
+starter = ["a"]
+
+[network]
+ip = "0.0.0.0"
+port = 3002
+
+[locales.th]
+for_table = " โต๊ะ "
+because = " เพราะ "
+
+[locales.th.commands]
+"new order" = "สั่ง"
+cancel = "ยกเลิก"
+
+[database]
+backend = "memory"
+
+[[auth.tokens]]
+token = "pl3a53-h1r3-m3"
+name = "staff"
+role = "admin"
+
+[[auth.tokens]]
+token = "m4n4g3r"
+name = "manager"
+role = "manager"
+
+[[auth.tokens]]
+token = "w41t3r"
+name = "waiter"
+role = "waiter"
+"###,
+    );
+    App::new(config)
+}
+
+async fn get(uri: &str, token: &str) -> Result<(u16, String)> {
+    let req = Request::get(format!("http://localhost:3002{}", uri))
+        .header("Authorization", token)
+        .body(Body::empty())?;
+    let res = Client::new().request(req).await?;
+    let status = res.status().as_u16();
+    let full_body = hyper::body::to_bytes(res.into_body()).await?;
+    Ok((status, String::from_utf8(full_body.into_iter().collect())?))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_event_log() -> Result<()> {
+    let service = setup_service();
+    tokio::spawn(async move { service.serve().await });
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let client = RestaurantClient::new("http://localhost:3002/order".into())
+        .with_device_id("tablet-1".into());
+    client.request("new order for table 1: a * 1").await?;
+    client.request("new order for table 2: b * 1").await?;
+    client.request("cancel for table 1: a * 1 because kitchen mistake").await?;
+    client.request("new order for table 1: b * 2").await?;
+
+    let (status, _) = get("/events", "w41t3r").await?;
+    assert_eq!(status, 403);
+
+    let (status, body) = get("/events?table=1&limit=2", "m4n4g3r").await?;
+    assert_eq!(status, 200);
+    let page: serde_json::Value = serde_json::from_str(&body)?;
+    let versions: Vec<_> = page["events"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["version"].as_u64().unwrap())
+        .collect();
+    // Version 1 put the menus from the config on the menu
+    assert_eq!(versions, vec![2, 4]);
+    assert_eq!(page["events"][0]["actor"], "staff");
+    assert_eq!(page["events"][0]["device_id"], "tablet-1");
+    assert_eq!(page["next_cursor"], 5);
+
+    let (status, body) = get("/events?table=1&limit=2&from_version=5", "m4n4g3r").await?;
+    assert_eq!(status, 200);
+    let page: serde_json::Value = serde_json::from_str(&body)?;
+    assert_eq!(page["events"][0]["event_toro"], "new order for table 1: b * 2");
+    assert!(page["next_cursor"].is_null());
+
+    let (status, body) = get("/events?command=cancel", "pl3a53-h1r3-m3").await?;
+    assert_eq!(status, 200);
+    let page: serde_json::Value = serde_json::from_str(&body)?;
+    assert_eq!(page["events"].as_array().unwrap().len(), 1);
+
+    let (status, _) = get("/events?command=dance", "m4n4g3r").await?;
+    assert_eq!(status, 400);
+
+    let (status, _) = get("/tables/1?as_of_version=2", "w41t3r").await?;
+    assert_eq!(status, 403);
+
+    let (status, body) = get("/tables/1?as_of_version=2", "m4n4g3r").await?;
+    assert_eq!(status, 200);
+    let table: serde_json::Value = serde_json::from_str(&body)?;
+    assert_eq!(table["version"], 2);
+    assert_eq!(table["table"]["items"]["a"]["quantity"], 1);
+
+    let (_, body) = get("/tables/1?as_of_version=4", "m4n4g3r").await?;
+    let table: serde_json::Value = serde_json::from_str(&body)?;
+    assert!(table["table"]["items"].as_object().unwrap().is_empty());
+
+    let (_, body) = get("/tables/1", "m4n4g3r").await?;
+    let table: serde_json::Value = serde_json::from_str(&body)?;
+    assert_eq!(table["version"], 5);
+    assert_eq!(table["table"]["items"]["b"]["quantity"], 2);
+
+    let (_, body) = get("/tables/1?as_of=0", "m4n4g3r").await?;
+    let table: serde_json::Value = serde_json::from_str(&body)?;
+    assert_eq!(table["version"], 0);
+
+    let (status, _) = get("/tables/1?as_of=0&as_of_version=1", "m4n4g3r").await?;
+    assert_eq!(status, 400);
+
+    // Oldest order first. The cancelled one is gone.
+    let (status, body) = get("/kitchen/queue", "w41t3r").await?;
+    assert_eq!(status, 200);
+    let queue: serde_json::Value = serde_json::from_str(&body)?;
+    assert_eq!(queue["version"], 5);
+    let lines: Vec<_> = queue["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| {
+            let table = i["table_id"].as_u64().unwrap();
+            (table, i["menu"].as_str().unwrap(), i["quantity"].as_i64().unwrap())
+        })
+        .collect();
+    assert_eq!(lines, vec![(2, "b", 1), (1, "b", 2)]);
+    assert!(queue["items"][0]["eta"].as_u64().unwrap() > 0);
+
+    let res = client.request("check kitchen").await?;
+    assert!(res.starts_with("Kitchen:\nTable 2: b * 1 in "), "{}", res);
+
+    let (status, _) = get("/reports/sales", "w41t3r").await?;
+    assert_eq!(status, 403);
+
+    let (status, body) = get("/reports/sales?from=0", "m4n4g3r").await?;
+    assert_eq!(status, 200);
+    let report: serde_json::Value = serde_json::from_str(&body)?;
+    assert_eq!(report["menus"][0]["menu"], "b");
+    assert_eq!(report["menus"][0]["net"], 3);
+    assert_eq!(report["menus"][1]["cancelled"], 1);
+    assert_eq!(report["menus"][1]["net"], 0);
+    assert_eq!(report["tables"][0]["table_id"], 1);
+    assert_eq!(report["tables"][0]["ordered"], 3);
+
+    let (status, body) = get("/reports/sales?format=csv", "m4n4g3r").await?;
+    assert_eq!(status, 200);
+    assert!(body.starts_with("kind,key,ordered,cancelled,net\nmenu,b,3,0,3\nmenu,a,1,1,0\n"));
+
+    let (_, body) = get("/reports/sales?to=1", "m4n4g3r").await?;
+    let report: serde_json::Value = serde_json::from_str(&body)?;
+    assert!(report["menus"].as_array().unwrap().is_empty());
+
+    let (status, _) = get("/reports/sales?format=pdf", "m4n4g3r").await?;
+    assert_eq!(status, 400);
+
+    let (status, body) = get("/reports/cancellations", "m4n4g3r").await?;
+    assert_eq!(status, 200);
+    let report: serde_json::Value = serde_json::from_str(&body)?;
+    assert_eq!(report["total"], 1);
+    assert_eq!(report["reasons"][0]["name"], "kitchen mistake");
+    assert_eq!(report["staff"][0]["name"], "staff");
+    assert_eq!(report["lines"][0]["menu"], "a");
+
+    let (_, body) = get("/reports/cancellations?format=csv", "m4n4g3r").await?;
+    assert_eq!(body, "reason,menu,staff,quantity\nkitchen mistake,a,staff,1\n");
+
+    // Nobody counted a before, so the earlier orders don't take from it
+    assert_eq!(client.request("restock: a * 2").await?, "Order received");
+    let res = client.request("new order for table 3: a * 1, a * 2").await?;
+    assert_eq!(res, "Sorry. Only 2 a left.");
+    client.request("new order for table 3: a * 2").await?;
+    let res = client.request("new order for table 4: a * 1").await?;
+    assert_eq!(res, "Sorry. a is sold out.");
+    client.request("cancel for table 3: a * 1").await?;
+    assert_eq!(client.request("new order for table 4: a * 1").await?, "Order received");
+    client.request("sold out: b").await?;
+    let res = client.request("new order for table 4: b * 1").await?;
+    assert_eq!(res, "Sorry. b is sold out.");
+
+    let res = client.request("new order for table 5: c * 1").await?;
+    assert_eq!(res, "We don't serve [\"c\"]");
+    client.request("add menu: c").await?;
+    let res = client.request("add menu: c").await?;
+    assert_eq!(res, "We already serve [\"c\"]");
+    client.request("set price: c at 150").await?;
+    assert_eq!(client.request("new order for table 5: c * 1").await?, "Order received");
+    client.request("rename menu: c to c c").await?;
+
+    let (status, body) = get("/menu", "w41t3r").await?;
+    assert_eq!(status, 200);
+    let menu: serde_json::Value = serde_json::from_str(&body)?;
+    assert_eq!(menu["version"], 14);
+    let names: Vec<_> = menu["menus"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["a", "b", "c c"]);
+    assert_eq!(menu["menus"][2]["price"], 150);
+
+    let (status, _) = get("/menu?as_of_version=11", "w41t3r").await?;
+    assert_eq!(status, 403);
+    let (_, body) = get("/menu?as_of_version=11", "m4n4g3r").await?;
+    let menu: serde_json::Value = serde_json::from_str(&body)?;
+    assert_eq!(menu["version"], 11);
+    assert_eq!(menu["menus"][2]["name"], "c");
+    assert!(menu["menus"][2]["price"].is_null());
+
+    client.request("retire menu: c c").await?;
+    let (_, body) = get("/menu", "w41t3r").await?;
+    let menu: serde_json::Value = serde_json::from_str(&body)?;
+    assert_eq!(menu["menus"].as_array().unwrap().len(), 2);
+
+    client.request("restock: a * 5").await?;
+    let res = client.request("new order for table 6: a * 1 hold snacks").await?;
+    assert_eq!(res, "Unknown course: snack");
+    client.request("new order for table 6: a * 1 hold starters").await?;
+    let res = client.request("check for table 6").await?;
+    assert_eq!(res, "Table 6:\na * 1 on hold\n");
+    let (_, body) = get("/kitchen/queue", "w41t3r").await?;
+    assert!(!body.contains("\"table_id\":6"));
+    client.request("fire course starters for table 6").await?;
+    let res = client.request("check for table 6").await?;
+    assert!(res.starts_with("Table 6:\na * 1 in "), "{}", res);
+    let (_, body) = get("/kitchen/queue", "w41t3r").await?;
+    assert!(body.contains("\"table_id\":6"));
+
+    client.request("restock: b * 10").await?;
+    for table in 3..=4 {
+        let order = format!("new order for table {}: b * 2", table);
+        client.request(order).await?;
+    }
+    let res = client.request("cancel for tables 3-5: b * 1").await?;
+    assert_eq!(res, "Order received for 3 tables");
+    let res = client.request("check for tables 3-4: b").await?;
+    assert!(res.starts_with("Table 3:\nb * 1 in "), "{}", res);
+    assert!(res.contains("\n\nTable 4:\nb * 1 in "), "{}", res);
+    let res = client.request("check for all tables: b").await?;
+    assert!(res.contains("Table 4:\nb * 1") && !res.contains("Table 9:"), "{}", res);
+    let res = client.request("cancel for tables 8-10: b * 1").await?;
+    assert!(res.starts_with("We don't have table 10."), "{}", res);
+    // Stored as one event for each table
+    let (_, body) = get("/events?table=5", "m4n4g3r").await?;
+    let page: serde_json::Value = serde_json::from_str(&body)?;
+    let last = page["events"].as_array().unwrap().last().unwrap();
+    assert_eq!(last["event_toro"], "cancel for table 5: b * 1");
+
+    let sloppy = "New  Order for Table #7: A x 1";
+    assert_eq!(client.request(sloppy).await?, "Invalid order string");
+    let lenient = RestaurantClient::new("http://localhost:3002/order".into())
+        .with_toro_mode("lenient".into());
+    assert_eq!(lenient.request(sloppy).await?, "Order received");
+    let res = lenient.request("check for table 7").await?;
+    assert!(res.starts_with("Table 7:\na * 1 in "), "{}", res);
+    let loose = RestaurantClient::new("http://localhost:3002/order".into())
+        .with_toro_mode("loose".into());
+    assert_eq!(loose.request(sloppy).await?, "X-Toro-Mode is strict or lenient.");
+
+    let thai = RestaurantClient::new("http://localhost:3002/order".into())
+        .with_toro_locale("th".into());
+    assert_eq!(thai.request("สั่ง โต๊ะ 8: a * 2").await?, "Order received");
+    thai.request("ยกเลิก โต๊ะ 8: a * 1 เพราะ ลูกค้าเปลี่ยนใจ").await?;
+    let (_, body) = get("/events?table=8", "m4n4g3r").await?;
+    let page: serde_json::Value = serde_json::from_str(&body)?;
+    assert_eq!(page["events"][0]["event_toro"], "new order for table 8: a * 2");
+    assert_eq!(
+        page["events"][1]["event_toro"],
+        "cancel for table 8: a * 1 because ลูกค้าเปลี่ยนใจ"
+    );
+    let french = RestaurantClient::new("http://localhost:3002/order".into())
+        .with_toro_locale("fr".into());
+    assert_eq!(french.request("yeet").await?, "We don't speak fr.");
+
+    // Orders are limited even without [restaurant.max_quantities]
+    let res = client.request("new order for table 7: a * 600, b * 401").await?;
+    assert_eq!(res, "Too many in one order. At most 1000.");
+
+    // Audit queries by who sent an order, from where, and as part of what
+    let req = Request::post("http://localhost:3002/order")
+        .header("Authorization", "w41t3r")
+        .header("X-Correlation-Id", "split-bill-42")
+        .body(Body::from("new order for table 9: a * 1"))?;
+    Client::new().request(req).await?;
+    let (_, body) = get("/events?actor=waiter", "m4n4g3r").await?;
+    let page: serde_json::Value = serde_json::from_str(&body)?;
+    assert_eq!(page["events"].as_array().unwrap().len(), 1);
+    assert_eq!(page["events"][0]["event_toro"], "new order for table 9: a * 1");
+    let (_, body) = get("/events?correlation_id=split-bill-42", "m4n4g3r").await?;
+    let page: serde_json::Value = serde_json::from_str(&body)?;
+    assert_eq!(page["events"][0]["actor"], "waiter");
+    let (_, body) = get("/events?device_id=tablet-1&limit=1000", "m4n4g3r").await?;
+    let page: serde_json::Value = serde_json::from_str(&body)?;
+    let events = page["events"].as_array().unwrap();
+    assert!(events.len() >= 4);
+    assert!(events.iter().all(|e| e["device_id"] == "tablet-1"));
+    let (_, body) = get("/events?actor=waiter&device_id=tablet-1", "m4n4g3r").await?;
+    let page: serde_json::Value = serde_json::from_str(&body)?;
+    assert!(page["events"].as_array().unwrap().is_empty());
+    Ok(())
+}
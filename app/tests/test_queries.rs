@@ -13,9 +13,12 @@ menus = ["a", "b", "c", "d", "e", "f"]
 [network]
 ip = "0.0.0.0"
 port = 3001
+
+[database]
+backend = "memory"
 "###,
     );
-    App::new(config, "./event_test.db".into())
+    App::new(config)
 }
 
 async fn gen_client(table: u32) -> Result<()> {
@@ -46,12 +49,6 @@ async fn gen_client(table: u32) -> Result<()> {
 
 #[tokio::test(flavor = "multi_thread")]
 async fn test_queries() -> Result<()> {
-    let result = inner().await;
-    std::fs::remove_file("./event_test.db").ok();
-    result
-}
-
-async fn inner() -> Result<()> {
     let service = setup_service();
     let j_service = tokio::spawn(async move { service.serve().await });
     let mut handles = Vec::new();
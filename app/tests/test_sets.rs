@@ -0,0 +1,112 @@
+use app::{config::Config, App};
+use client::RestaurantClient;
+use hyper::{Body, Client, Request};
+
+use anyhow::Result;
+
+fn setup_service() -> App {
+    let config = Config::from_toml_string(
+        r###"
+[restaurant]
+table = 10
+menus = ["ramen", "miso soup", "tea"]
+
+[restaurant.sets]
+"ramen set" = ["ramen", "miso soup", "tea"]
+"double tea" = ["tea", "tea"]
+
+[restaurant.aliases]
+miso = "miso soup"
+
+[restaurant.max_quantities]
+per_order = 6
+per_menu = { "ramen set" = 3 }
+
+[network]
+ip = "0.0.0.0"
+port = 3004
+
+[database]
+backend = "memory"
+
+[[auth.tokens]]
+token = "pl3a53-h1r3-m3"
+name = "staff"
+role = "admin"
+"###,
+    );
+    App::new(config)
+}
+
+async fn get(uri: &str) -> Result<String> {
+    let req = Request::get(format!("http://localhost:3004{}", uri))
+        .header("Authorization", "pl3a53-h1r3-m3")
+        .body(Body::empty())?;
+    let res = Client::new().request(req).await?;
+    let full_body = hyper::body::to_bytes(res.into_body()).await?;
+    Ok(String::from_utf8(full_body.into_iter().collect())?)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_set_menus() -> Result<()> {
+    let service = setup_service();
+    tokio::spawn(async move { service.serve().await });
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let client = RestaurantClient::new("http://localhost:3004/order".into());
+    client.request("restock: tea * 4").await?;
+    let res = client.request("new order for table 1: ramen set * 1, double tea * 2").await?;
+    assert_eq!(res, "Sorry. Only 4 tea left.");
+    let res = client.request("new order for table 1: ramen set * 2, ramen * 1").await?;
+    assert_eq!(res, "Order received");
+    let res = client.request("check for table 1: ramen, tea").await?;
+    assert!(res.starts_with("Table 1:\nramen * 3 in "), "{}", res);
+    assert!(res.contains("\ntea * 2 in "), "{}", res);
+
+    // Stored with the components, so changing a set later doesn't change past orders
+    let events: serde_json::Value = serde_json::from_str(&get("/events?table=1").await?)?;
+    let stored = "new order for table 1: ramen * 2, miso soup * 2, tea * 2, ramen * 1 \
+        with sets ramen set * 2";
+    assert_eq!(events["events"][0]["event_toro"], stored);
+    let res = client.request("new order for table 1: ramen * 1 with sets ramen set * 1").await?;
+    assert_eq!(res, "Order sets by their names. We fill in the rest.");
+
+    // The whole set goes, the ramen ordered on its own stays
+    client.request("cancel for table 1: ramen set * 2").await?;
+    let res = client.request("check for table 1").await?;
+    assert!(res.starts_with("Table 1:\nramen * 1 in "), "{}", res);
+    assert_eq!(res.lines().count(), 2);
+
+    // Sets are on the menu so they can have a price
+    let menu: serde_json::Value = serde_json::from_str(&get("/menu").await?)?;
+    let names: Vec<_> = menu["menus"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["double tea", "miso soup", "ramen", "ramen set", "tea"]);
+
+    // Stored under the names on the menu
+    client.request("new order for table 2: Miso * 1, Ramen Set * 1").await?;
+    let res = client.request("check for table 2: MISO SOUP").await?;
+    assert!(res.starts_with("Table 2:\nmiso soup * 2 in "), "{}", res);
+    let res = client.request("new order for table 2: ramne * 1, pizza * 1").await?;
+    assert_eq!(res, "We don't serve [\"ramne\", \"pizza\"]. Did you mean [\"ramen\"]?");
+
+    // Nobody orders zero or less, and nobody orders too many at once
+    let res = client.request("new order for table 3: ramen * 0").await?;
+    assert_eq!(res, "Quantities must be more than zero.");
+    let res = client.request("cancel for table 2: ramen * -1").await?;
+    assert_eq!(res, "Quantities must be more than zero.");
+    let res = client.request("new order for table 3: ramen set * 2, Ramen Set * 2").await?;
+    assert_eq!(res, "Too many ramen set. At most 3 in one order.");
+    let res = client.request("new order for table 3: tea * 4, ramen * 3").await?;
+    assert_eq!(res, "Too many in one order. At most 6.");
+    let res = client.request("new order for table 3: tea * 9223372036854775807, tea * 1").await?;
+    assert_eq!(res, "At most 1000000 of a menu at once.");
+    // Only new orders are limited
+    let res = client.request("cancel for table 2: ramen * 10").await?;
+    assert_eq!(res, "Order received");
+    Ok(())
+}
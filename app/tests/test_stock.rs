@@ -0,0 +1,57 @@
+use app::{config::Config, App};
+use client::RestaurantClient;
+
+use anyhow::Result;
+use std::path::Path;
+
+// On disk, so that writing takes long enough for orders to pile up
+fn setup_service(db_file: &Path) -> App {
+    let config = Config::from_toml_string(&format!(
+        r###"
+[restaurant]
+table = 50
+menus = ["ramen"]
+
+[network]
+ip = "0.0.0.0"
+port = 3007
+
+[database]
+path = "{}"
+"###,
+        db_file.display()
+    ));
+    App::new(config)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_racing_for_the_last_one() -> Result<()> {
+    let pid = std::process::id();
+    let db_file = std::env::temp_dir().join(format!("restaurant_stock_{}.db", pid));
+    std::fs::remove_file(&db_file).ok();
+    let service = setup_service(&db_file);
+    tokio::spawn(async move { service.serve().await });
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let client = RestaurantClient::new("http://localhost:3007/order".into());
+    assert_eq!(client.request("restock: ramen * 1").await?, "Order received");
+    let orders: Vec<_> = (0..50)
+        .map(|table| {
+            let client = RestaurantClient::new("http://localhost:3007/order".into());
+            tokio::spawn(async move {
+                client
+                    .request(format!("new order for table {}: ramen * 1", table))
+                    .await
+            })
+        })
+        .collect();
+    let mut answers = Vec::new();
+    for order in orders {
+        answers.push(order.await??);
+    }
+    std::fs::remove_file(&db_file).ok();
+    let sold_out = answers.iter().filter(|a| *a == "Sorry. ramen is sold out.");
+    assert_eq!(sold_out.count(), 49, "{:?}", answers);
+    assert!(answers.contains(&"Order received".to_string()));
+    Ok(())
+}
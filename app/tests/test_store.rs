@@ -0,0 +1,79 @@
+mod common;
+
+use app::{
+    config::{Config, DatabaseConfig},
+    db::Db,
+    memory_store::MemoryStore,
+    App,
+};
+use client::RestaurantClient;
+
+use common::{postgres::PgServer, store_suite::CASES};
+
+#[test]
+fn test_sqlite_store() {
+    for (name, case) in CASES {
+        let path = std::env::temp_dir()
+            .join(format!("restaurant_store_{}_{}.db", name, std::process::id()));
+        std::fs::remove_file(&path).ok();
+        case(&Db::init(&path, &DatabaseConfig::default()).unwrap());
+        // WAL mode leaves these behind if a connection was still opening when the pool went away
+        for suffix in ["", "-wal", "-shm"] {
+            let mut file = path.clone().into_os_string();
+            file.push(suffix);
+            std::fs::remove_file(file).ok();
+        }
+    }
+}
+
+#[test]
+fn test_memory_store() {
+    for (_, case) in CASES {
+        case(&MemoryStore::new());
+    }
+}
+
+// Run with `cargo test -- --ignored` and a postgres server, see common/postgres.rs
+#[test]
+#[ignore = "needs a postgres server"]
+fn test_postgres_store() {
+    let server = PgServer::start();
+    for (_, case) in CASES {
+        case(&server.fresh_store());
+    }
+}
+
+// Not a tokio::test since the postgres client used for setting up can't run inside a runtime
+#[test]
+#[ignore = "needs a postgres server"]
+fn test_postgres_service() -> anyhow::Result<()> {
+    let server = PgServer::start();
+    let config = Config::from_toml_string(&format!(
+        r###"
+[restaurant]
+table = 10
+menus = ["a", "b"]
+
+[network]
+ip = "0.0.0.0"
+port = 3003
+
+[database]
+backend = "postgres"
+url = "{}"
+"###,
+        server.fresh_database()
+    ));
+    let service = App::new(config);
+    tokio::runtime::Runtime::new()?.block_on(async move {
+        tokio::spawn(async move { service.serve().await });
+        tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+        let client = RestaurantClient::new("http://localhost:3003/order".into());
+        client.request("new order for table 1: a * 2").await?;
+        client.request("cancel for table 1: a * 1").await?;
+        let res = client.request("check for table 1: a").await?;
+        assert!(res.starts_with("Table 1:\na * 1"), "{}", res);
+        Ok(())
+    })
+}
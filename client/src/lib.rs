@@ -4,6 +4,9 @@ use hyper::{client::HttpConnector, Client, Request, Method};
 pub struct RestaurantClient {
     client: Client<HttpConnector>,
     entry_point: String,
+    device_id: Option<String>,
+    toro_mode: Option<String>,
+    toro_locale: Option<String>,
 }
 
 impl RestaurantClient {
@@ -11,18 +14,47 @@ impl RestaurantClient {
         Self {
             client: Client::new(),
             entry_point,
+            device_id: None,
+            toro_mode: None,
+            toro_locale: None,
         }
     }
 
+    pub fn with_device_id(mut self, device_id: String) -> Self {
+        self.device_id = Some(device_id);
+        self
+    }
+
+    // "lenient" lets the service accept sloppy orders
+    pub fn with_toro_mode(mut self, mode: String) -> Self {
+        self.toro_mode = Some(mode);
+        self
+    }
+
+    // Orders are written in this locale from the service config
+    pub fn with_toro_locale(mut self, locale: String) -> Self {
+        self.toro_locale = Some(locale);
+        self
+    }
+
     pub async fn request<T>(&self, order: T) -> Result<String>
     where
         hyper::Body: From<T>,
     {
-        let req = Request::builder()
+        let mut req = Request::builder()
             .method(Method::POST)
             .uri(&self.entry_point)
-            .header("Authorization", "pl3a53-h1r3-m3")
-            .body(order.into())?;
+            .header("Authorization", "pl3a53-h1r3-m3");
+        if let Some(device_id) = &self.device_id {
+            req = req.header("X-Device-Id", device_id);
+        }
+        if let Some(mode) = &self.toro_mode {
+            req = req.header("X-Toro-Mode", mode);
+        }
+        if let Some(locale) = &self.toro_locale {
+            req = req.header("X-Toro-Locale", locale);
+        }
+        let req = req.body(order.into())?;
         let res = self.client.request(req).await?;
         let full_body = hyper::body::to_bytes(res.into_body()).await?;
         Ok(String::from_utf8(full_body.into_iter().collect())?)
@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use toro::{Mode, Toro};
+
+// Whatever parses is written back as an order that parses to the same thing
+fuzz_target!(|input: &str| {
+    for mode in [Mode::Strict, Mode::Lenient] {
+        if let Ok(toro) = Toro::parse(input, mode) {
+            let printed = toro.to_toro_string();
+            let reparsed = Toro::from_toro_string(&printed)
+                .unwrap_or_else(|e| panic!("{:?} was written as {:?}: {}", input, printed, e));
+            assert_eq!(reparsed, toro, "{:?} was written as {:?}", input, printed);
+        }
+    }
+});
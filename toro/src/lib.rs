@@ -1,165 +1,436 @@
-use std::{fmt::Display, str::FromStr};
+use std::{collections::HashMap, fmt::Display, ops::RangeInclusive, str::FromStr, sync::OnceLock};
 
 use anyhow::{anyhow, bail, Result};
 
+pub mod syntax;
+
 pub type MenuName = String;
 type Quantity = i64;
+// In the smallest unit of the currency
+pub type Price = u64;
 type TableId = usize;
 
-trait MySplit<'a> {
-    fn my_split<'b>(&'a self, p: &'b str) -> (Option<&'a str>, Option<&'a str>);
-}
-
-impl<'a> MySplit<'a> for &'a str {
-    fn my_split<'b>(&'a self, p: &'b str) -> (Option<&'a str>, Option<&'a str>) {
-        let mut iter = self.splitn(2, p);
-        (iter.next(), iter.next())
-    }
-}
-
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Command {
     New,
     Cancel,
     Check,
+    // Everything the kitchen still has to cook, across all tables
+    CheckKitchen,
+    // More of some menus came in
+    Restock,
+    // Some menus ran out
+    SoldOut,
+    // Menu changes
+    AddMenu,
+    RenameMenu,
+    RetireMenu,
+    SetPrice,
+    // Start cooking a course that was held
+    Fire,
     Yeet,
 }
 
+impl Command {
+    pub const ALL: [Command; 12] = [
+        Command::New,
+        Command::Cancel,
+        Command::Check,
+        Command::CheckKitchen,
+        Command::Restock,
+        Command::SoldOut,
+        Command::AddMenu,
+        Command::RenameMenu,
+        Command::RetireMenu,
+        Command::SetPrice,
+        Command::Fire,
+        Command::Yeet,
+    ];
+
+    pub fn edits_menu(&self) -> bool {
+        use Command::*;
+        matches!(self, AddMenu | RenameMenu | RetireMenu | SetPrice)
+    }
+
+    pub fn from_phrase(s: &str, keywords: &Keywords) -> Result<Self> {
+        let s = s.trim();
+        Command::ALL
+            .into_iter()
+            .find(|c| keywords.command(*c) == s)
+            .ok_or(anyhow!("Unknown command: {}", s))
+    }
+}
+
 impl FromStr for Command {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Command::from_phrase(s, english())
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use Command::*;
-        match s.trim() {
-            "new order" => Ok(New),
-            "cancel" => Ok(Cancel),
-            "check" => Ok(Check),
-            "yeet" => Ok(Yeet),
-            c => Err(anyhow!("Unknown command: {}", c)),
+        let s = match self {
+            New => "new order",
+            Cancel => "cancel",
+            Check => "check",
+            CheckKitchen => "check kitchen",
+            Restock => "restock",
+            SoldOut => "sold out",
+            AddMenu => "add menu",
+            RenameMenu => "rename menu",
+            RetireMenu => "retire menu",
+            SetPrice => "set price",
+            Fire => "fire course",
+            Yeet => "yeet",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// The words an order is written with. The default is English, which is what gets stored.
+// Separators and the `for_*` words are matched without the spaces around them,
+// the other words as they are, so " because " doesn't match inside a menu name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keywords {
+    commands: HashMap<Command, String>,
+    pub for_table: String,
+    pub for_tables: String,
+    pub for_all_tables: String,
+    // Between the first and the last table
+    pub range: String,
+    // Between the command and the parameters
+    pub params: String,
+    // Between the menus
+    pub list: String,
+    // Between a menu and its quantity
+    pub times: String,
+    pub because: String,
+    pub hold: String,
+    // Between the components of set menus and the sets they came from
+    pub sets: String,
+    pub to: String,
+    pub at: String,
+}
+
+impl Default for Keywords {
+    fn default() -> Self {
+        Self {
+            commands: Command::ALL.into_iter().map(|c| (c, c.to_string())).collect(),
+            for_table: " for table ".into(),
+            for_tables: " for tables ".into(),
+            for_all_tables: " for all tables ".into(),
+            range: "-".into(),
+            params: ": ".into(),
+            list: ", ".into(),
+            times: " * ".into(),
+            because: " because ".into(),
+            hold: " hold ".into(),
+            sets: " with sets ".into(),
+            to: " to ".into(),
+            at: " at ".into(),
         }
     }
 }
 
+impl Keywords {
+    pub fn command(&self, command: Command) -> &str {
+        &self.commands[&command]
+    }
+
+    pub fn with_command(mut self, command: Command, phrase: &str) -> Self {
+        self.commands.insert(command, phrase.trim().into());
+        self
+    }
+}
+
+fn english() -> &'static Keywords {
+    static ENGLISH: OnceLock<Keywords> = OnceLock::new();
+    ENGLISH.get_or_init(Keywords::default)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Param {
     MenuQuantities(Vec<(MenuName, Quantity)>),
     Menu(Vec<MenuName>),
+    // From, to
+    Rename(MenuName, MenuName),
+    Prices(Vec<(MenuName, Price)>),
 }
 
-fn get_menu_quant(s: &str) -> Result<Param> {
-    let menu_quant: Option<Vec<_>> = s
-        .trim()
-        .split(',')
-        // [.., .., ..]
-        .map(|e| e.trim())
-        // [Some(m, q), Some(m, q), ...] or [None, None, ...]
-        .map(|e| e.split_once('*'))
-        .collect();
+impl FromStr for Param {
+    type Err = anyhow::Error;
 
-    if let Some(menu_quant) = menu_quant {
-        let menu_quant: Result<Vec<_>> = menu_quant
-            .into_iter()
-            .map(|(m, q)| match q.trim().parse() {
-                Ok(q) => Ok((m.trim().into(), q)),
-                Err(_) => Err(anyhow!("error parsing number {}", q)),
-            })
-            .collect();
-        return menu_quant.map(|e| Param::MenuQuantities(e));
-    }
-    bail!("Inconsistent parameters: some parameter doesn't form a menu * quantity pair.");
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(syntax::parse_items(s, english())?.into())
+    }
 }
 
-fn get_menu(s: &str) -> Result<Param> {
-    let menu: Vec<_> = s.trim().split(',').map(|e| e.trim().into()).collect();
-    Ok(Param::Menu(menu))
+impl Param {
+    pub fn render(&self, keywords: &Keywords) -> String {
+        let entries: Vec<String> = match self {
+            Param::MenuQuantities(v) => v
+                .iter()
+                .map(|(m, q)| format!("{}{}{}", m, keywords.times, q))
+                .collect(),
+            Param::Menu(v) => v.clone(),
+            Param::Rename(from, to) => vec![format!("{}{}{}", from, keywords.to, to)],
+            Param::Prices(v) => v
+                .iter()
+                .map(|(m, p)| format!("{}{}{}", m, keywords.at, p))
+                .collect(),
+        };
+        entries.join(&keywords.list)
+    }
 }
 
-impl FromStr for Param {
+impl Display for Param {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.render(english()))
+    }
+}
+
+// How forgiving the parser is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    // Exactly like `to_toro_string` writes it
+    #[default]
+    Strict,
+    // Any case and spacing, `x` or `×` for `*` and `#` before the table id
+    Lenient,
+}
+
+impl FromStr for Mode {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.is_empty() {
-            bail!("Parameter must not be an empty string.");
+        match s.trim().to_lowercase().as_str() {
+            "strict" => Ok(Mode::Strict),
+            "lenient" => Ok(Mode::Lenient),
+            m => Err(anyhow!("Unknown mode: {}", m)),
         }
-        // Input: menu, menu, menu...
-        // Input: menu * quant, menu * quant, menu * quant,...
-        if s.contains('*') {
-            get_menu_quant(s)
-        } else {
-            get_menu(s)
+    }
+}
+
+// Input: menu x 2, menu x2, menu × 2 or menu*2
+fn loosen_item(item: &str, keywords: &Keywords) -> String {
+    let rest = item.trim_end_matches(|c: char| c.is_ascii_digit());
+    let quantity = &item[rest.len()..];
+    let rest = rest.trim_end();
+    let menu = rest
+        .strip_suffix(keywords.times.trim())
+        .or_else(|| rest.strip_suffix('×'))
+        .or_else(|| rest.strip_suffix(" x"));
+    match menu {
+        Some(menu) if !quantity.is_empty() => {
+            format!("{}{}{}", menu.trim(), keywords.times, quantity)
         }
+        _ => item.to_string(),
     }
 }
 
-impl Display for Param {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+// Rewrites a sloppy order the way the strict parser reads it.
+// The reason keeps its case, everything else is folded.
+fn loosen(input: &str, keywords: &Keywords) -> String {
+    let input = input.split_whitespace().collect::<Vec<_>>().join(" ");
+    let (left, right) = match input.split_once(keywords.params.trim()) {
+        Some((left, right)) => (left, Some(right)),
+        None => (input.as_str(), None),
+    };
+    // Ranges of tables are often written with a dash that isn't on the keyboard
+    let left = left
+        .to_lowercase()
+        .replace('#', "")
+        .replace('–', keywords.range.trim());
+    let right = match right {
+        Some(right) => right,
+        None => return left,
+    };
+    // Same length as `right`, so the positions found here work there too
+    let folded = right.to_ascii_lowercase();
+    let because = keywords.because.to_ascii_lowercase();
+    let (param, reason) = match folded.find(&because) {
+        Some(i) => (&right[..i], Some(&right[i + because.len()..])),
+        None => (right, None),
+    };
+    let param = param.to_lowercase();
+    let (param, hold) = match param.split_once(keywords.hold.as_str()) {
+        Some((param, hold)) => (param, format!("{}{}", keywords.hold, hold)),
+        None => (param.as_str(), String::new()),
+    };
+    let items: Vec<String> = param
+        .split(keywords.list.trim())
+        .map(|i| loosen_item(i.trim(), keywords))
+        .collect();
+    let reason = reason.map(|r| format!("{}{}", keywords.because, r));
+    format!(
+        "{}{}{}{}{}",
+        left,
+        keywords.params,
+        items.join(&keywords.list),
+        hold,
+        reason.unwrap_or_default()
+    )
+}
+
+// Which tables an order is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TableSelector {
+    // Orders for the whole restaurant, like restock
+    #[default]
+    None,
+    One(TableId),
+    // The first and the last, both included
+    Range(TableId, TableId),
+    All,
+}
+
+impl TableSelector {
+    pub fn is_none(&self) -> bool {
+        matches!(self, Self::None)
+    }
+
+    // The table, if there is only one
+    pub fn single(&self) -> Option<TableId> {
         match self {
-            Param::MenuQuantities(v) => {
-                let mut iter = v.iter().peekable();
-                while let Some((m, q)) = iter.next() {
-                    write!(f, "{} * {}", m, q)?;
-                    if !iter.peek().is_none() {
-                        write!(f, ", ")?;
-                    }
-                }
-                Ok(())
-            }
-            Param::Menu(v) => {
-                let mut iter = v.iter().peekable();
-                while let Some(m) = iter.next() {
-                    write!(f, "{}", m)?;
-                    if !iter.peek().is_none() {
-                        write!(f, ", ")?;
-                    }
-                }
-                Ok(())
+            Self::One(t) => Some(*t),
+            _ => None,
+        }
+    }
+
+    // The largest table asked for by number
+    pub fn last(&self) -> Option<TableId> {
+        match self {
+            Self::One(last) | Self::Range(_, last) => Some(*last),
+            Self::None | Self::All => None,
+        }
+    }
+
+    // Every table selected in a restaurant with `n_table` tables
+    pub fn ids(&self, n_table: usize) -> RangeInclusive<TableId> {
+        match *self {
+            Self::One(t) => t..=t,
+            Self::Range(first, last) => first..=last,
+            Self::All if n_table > 0 => 0..=n_table - 1,
+            // Empty
+            Self::None | Self::All => RangeInclusive::new(1, 0),
+        }
+    }
+
+    pub fn render(&self, keywords: &Keywords) -> String {
+        match self {
+            Self::None => String::new(),
+            Self::One(t) => format!("{}{}", keywords.for_table, t),
+            Self::Range(first, last) => {
+                format!("{}{}{}{}", keywords.for_tables, first, keywords.range, last)
             }
+            Self::All => keywords.for_all_tables.trim_end().into(),
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Toro {
     pub command: Command,
-    pub table_id: Option<TableId>,
+    pub tables: TableSelector,
     pub param: Option<Param>,
-}
-
-// Parse left side of ':' which can contain command or table id
-fn parse_left(s: Option<&str>) -> Result<(Command, Option<TableId>)> {
-    // Input: command for table id
-    let s = s.ok_or(anyhow!("Left side must not be empty."))?;
-    let s = s.trim();
-    let (command, table_id) = s.my_split("for table");
-    let command = command.ok_or(anyhow!("empty command"))?.trim().parse()?;
-    let table_id = table_id.map(|t| t.trim().parse()).transpose()?;
-    Ok((command, table_id))
-}
-
-// Parse right side of ':' which can only be parameters
-fn parse_right(s: Option<&str>) -> Result<Option<Param>> {
-    s.map(|inner| inner.parse()).transpose()
+    // Why something got cancelled. Only cancel has one.
+    pub reason: Option<String>,
+    // Courses of a new order that wait to be fired
+    pub hold: Vec<String>,
+    // The course to fire
+    pub course: Option<String>,
+    // Set menus as they were ordered or cancelled. `param` has their components.
+    pub sets: Vec<(MenuName, Quantity)>,
 }
 
 impl Toro {
     fn integrity_check(&self) -> Result<()> {
         use Command::*;
+        if self.reason.is_some() && !matches!(self.command, Cancel) {
+            bail!("Only cancel can have a reason.");
+        }
+        if !self.hold.is_empty() && !matches!(self.command, New) {
+            bail!("Only new order can hold courses.");
+        }
+        if !self.sets.is_empty() && !matches!(self.command, New | Cancel) {
+            bail!("Only new order and cancel can have sets.");
+        }
         match self.command {
             New => {
-                self.table_id.ok_or(anyhow!("new order command needs table id"))?;
+                match self.tables {
+                    TableSelector::One(_) => (),
+                    TableSelector::None => bail!("new order command needs table id"),
+                    _ => bail!("new order is for one table at a time"),
+                }
                 self.param
                     .as_ref()
                     .ok_or(anyhow!("new order command needs parameters"))?;
             }
             Cancel => {
-                self.table_id.ok_or(anyhow!("cancel command needs table id"))?;
+                if self.tables.is_none() {
+                    bail!("cancel command needs table id");
+                }
                 self.param
                     .as_ref()
                     .ok_or(anyhow!("cancel command needs parameters"))?;
             }
             Check => {
-                self.table_id.ok_or(anyhow!("check command needs table id"))?;
+                if self.tables.is_none() {
+                    bail!("check command needs table id");
+                }
+            }
+            Fire => {
+                if self.tables.is_none() {
+                    bail!("fire command needs table id");
+                }
+                match &self.course {
+                    Some(course) if !course.is_empty() => (),
+                    _ => bail!("fire command needs a course"),
+                }
+                if self.param.is_some() {
+                    bail!("fire command needs no parameters");
+                }
+            }
+            CheckKitchen => {
+                if !self.tables.is_none() || self.param.is_some() {
+                    bail!("check kitchen needs nothing else.");
+                }
+            }
+            Restock => {
+                if !self.tables.is_none() {
+                    bail!("restock is for the whole restaurant, not a table");
+                }
+                if !matches!(self.param, Some(Param::MenuQuantities(_))) {
+                    bail!("restock command needs menu * quantity parameters");
+                }
+            }
+            SoldOut => {
+                if !self.tables.is_none() {
+                    bail!("sold out is for the whole restaurant, not a table");
+                }
+                if !matches!(self.param, Some(Param::Menu(_))) {
+                    bail!("sold out command needs menu parameters");
+                }
+            }
+            AddMenu | RenameMenu | RetireMenu | SetPrice => {
+                if !self.tables.is_none() {
+                    bail!("{} is for the whole restaurant, not a table", self.command);
+                }
+                let fits = matches!(
+                    (self.command, &self.param),
+                    (AddMenu | RetireMenu, Some(Param::Menu(_)))
+                        | (RenameMenu, Some(Param::Rename(..)))
+                        | (SetPrice, Some(Param::Prices(_)))
+                );
+                if !fits {
+                    bail!("{} command needs different parameters", self.command);
+                }
             }
             Yeet => {
-                if self.table_id.is_some() || self.param.is_some() {
+                if !self.tables.is_none() || self.param.is_some() {
                     bail!("yeet needs nothing. Just only yeet.");
                 }
             }
@@ -167,46 +438,100 @@ impl Toro {
         Ok(())
     }
     pub fn from_toro_string(input: &str) -> Result<Self> {
-        // Input: command for table id: params
-        let input = input.trim();
-        let (left, right) = input.my_split(":");
-        let (command, table_id) = parse_left(left)?;
-        let param = parse_right(right)?;
-        let toro = Self {
-            command,
-            table_id,
-            param,
+        Self::parse_with(input, Mode::Strict, english())
+    }
+
+    pub fn parse(input: &str, mode: Mode) -> Result<Self> {
+        Self::parse_with(input, mode, english())
+    }
+
+    // Parses an order written with other keywords
+    pub fn parse_with(input: &str, mode: Mode, keywords: &Keywords) -> Result<Self> {
+        let loosened;
+        let input = match mode {
+            Mode::Strict => input,
+            Mode::Lenient => {
+                loosened = loosen(input, keywords);
+                &loosened
+            }
         };
+        let ast = syntax::parse(input, keywords)?;
+        ast.check_quantities()?;
+        let toro = Toro::from(ast);
+        toro.integrity_check()?;
+        Ok(toro)
+    }
+
+    // Orders stored before quantities were checked can have any quantity,
+    // so whoever replays them has to look out for those
+    pub fn from_stored_string(input: &str) -> Result<Self> {
+        let toro = Toro::from(syntax::parse(input, english())?);
         toro.integrity_check()?;
         Ok(toro)
     }
 
     pub fn to_toro_string(&self) -> String {
-        fn inner(toro: &Toro) -> Result<String> {
+        self.to_toro_string_in(english())
+    }
+
+    // Writes the order with other keywords
+    pub fn to_toro_string_in(&self, keywords: &Keywords) -> String {
+        fn inner(toro: &Toro, kw: &Keywords) -> Result<String> {
             use Command::*;
+            let tables = toro.tables.render(kw);
+            let param = || {
+                toro.param
+                    .as_ref()
+                    .map(|p| p.render(kw))
+                    .ok_or(anyhow!("param must exist"))
+            };
+            // The components, then the sets they came from
+            let items = || {
+                let items = param()?;
+                if toro.sets.is_empty() {
+                    return Ok::<_, anyhow::Error>(items);
+                }
+                let sets = Param::MenuQuantities(toro.sets.clone()).render(kw);
+                Ok(format!("{}{}{}", items, kw.sets, sets))
+            };
+            let command = kw.command(toro.command);
             let result = match toro.command {
-                New => format!(
-                    "new order for table {}: {}",
-                    toro.table_id.ok_or(anyhow!("table id must exist"))?,
-                    toro.param.as_ref().ok_or(anyhow!("param must exist"))?
-                ),
-                Cancel => format!(
-                    "cancel for table {}: {}",
-                    toro.table_id.ok_or(anyhow!("table id must exist"))?,
-                    toro.param.as_ref().ok_or(anyhow!("param must exist"))?
+                New => {
+                    let order = format!("{}{}{}{}", command, tables, kw.params, items()?);
+                    if toro.hold.is_empty() {
+                        order
+                    } else {
+                        format!("{}{}{}", order, kw.hold, toro.hold.join(&kw.list))
+                    }
+                }
+                Fire => format!(
+                    "{} {}{}",
+                    command,
+                    toro.course.as_ref().ok_or(anyhow!("course must exist"))?,
+                    tables
                 ),
+                Cancel => {
+                    let cancel = format!("{}{}{}{}", command, tables, kw.params, items()?);
+                    match &toro.reason {
+                        Some(reason) => format!("{}{}{}", cancel, kw.because, reason),
+                        None => cancel,
+                    }
+                }
                 Check => {
-                    let table_id = toro.table_id.ok_or(anyhow!("table id must exist"))?;
+                    let check = format!("{}{}", command, tables);
                     match &toro.param {
-                        Some(param) => format!("check for table {}: {}", table_id, param),
-                        None => format!("check for table {}", table_id),
+                        Some(_) => format!("{}{}{}", check, kw.params, param()?),
+                        None => check,
                     }
                 }
-                Yeet => "yeet".into(),
+                CheckKitchen | Yeet => command.into(),
+                Restock | SoldOut | AddMenu | RenameMenu | RetireMenu | SetPrice => {
+                    format!("{}{}{}", command, kw.params, param()?)
+                }
             };
             Ok(result)
         }
-        inner(self).expect("how did you messed this up?")
+        inner(self, keywords).expect("how did you messed this up?")
     }
 }
 
@@ -233,9 +558,23 @@ mod tests {
     const NEW_ORDER: &str = "new order for table 1: a * 1, b * 2, c c c * 3";
     const NEW_ORDER2: &str = "  new order  for table 1  :a* 1, b*2, c c c* 3";
     const CANCEL: &str = "cancel for table 1: a * 1, b * 2";
+    const NEW_ORDER_HOLD: &str = "new order for table 1: a * 1, b * 2 hold mains, desserts";
+    const NEW_ORDER_SETS: &str =
+        "new order for table 1: a * 2, b * 2, c * 1 with sets a b set * 2 hold mains";
+    const FIRE: &str = "fire course mains for table 1";
+    const CANCEL_BECAUSE: &str = "cancel for table 1: a * 1, b * 2 because out of stock";
     const CHECK: &str = "check for table 1: a, b, c";
     const CHECK_ALL: &str = "check for table 1";
     const YEET: &str = "yeet";
+    const CHECK_KITCHEN: &str = "check kitchen";
+    const RESTOCK: &str = "restock: a * 10, b * 5";
+    const SOLD_OUT: &str = "sold out: a, b";
+    const MENU_EDITS: [&str; 4] = [
+        "add menu: a, b b",
+        "rename menu: a to a a",
+        "retire menu: a",
+        "set price: a at 120, b b at 90",
+    ];
     const KRANGLED: &str = "what is this for table something: oh a semicolon;";
 
     #[test]
@@ -271,6 +610,10 @@ mod tests {
             Toro::from_toro_string(YEET).unwrap().command,
             Yeet
         ));
+        assert!(matches!(
+            Toro::from_toro_string(CHECK_KITCHEN).unwrap().command,
+            CheckKitchen
+        ));
     }
 
     #[test]
@@ -293,18 +636,220 @@ mod tests {
         assert!(Toro::from_toro_string(YEET).unwrap().param.is_none());
     }
 
+    #[test]
+    fn test_reason() {
+        let toro = Toro::from_toro_string(CANCEL_BECAUSE).unwrap();
+        assert_eq!(toro.reason.as_deref(), Some("out of stock"));
+        assert!(matches!(toro.param, Some(MenuQuantities(v)) if v.len() == 2));
+        assert!(Toro::from_toro_string(CANCEL).unwrap().reason.is_none());
+        assert!(Toro::from_toro_string("cancel for table 1: a * 1 because ").is_err());
+        assert!(Toro::from_toro_string("new order for table 1: a * 1 because hungry").is_err());
+        assert!(Toro::from_toro_string("check for table 1: a because why not").is_err());
+    }
+
+    #[test]
+    fn test_tables() {
+        let toro = Toro::from_toro_string("check for tables 10-14").unwrap();
+        assert_eq!(toro.tables, TableSelector::Range(10, 14));
+        assert_eq!(toro.tables.ids(20), 10..=14);
+        assert_eq!(toro.to_toro_string(), "check for tables 10-14");
+        let toro = Toro::from_toro_string("cancel for all tables: a * 1").unwrap();
+        assert_eq!(toro.tables, TableSelector::All);
+        assert_eq!(toro.tables.ids(3), 0..=2);
+        assert_eq!(toro.to_toro_string(), "cancel for all tables: a * 1");
+        let fire = Toro::from_toro_string("fire course mains for tables 1 - 2").unwrap();
+        assert_eq!(fire.course.as_deref(), Some("mains"));
+        assert_eq!(fire.tables.last(), Some(2));
+        assert!(Toro::from_toro_string("new order for tables 1-2: a * 1").is_err());
+        assert!(Toro::from_toro_string("check for tables 14-10").is_err());
+        assert!(Toro::from_toro_string("check for tables 10").is_err());
+        assert!(Toro::from_toro_string("check for all tables 1").is_err());
+        assert!(Toro::from_toro_string("restock for all tables: a * 1").is_err());
+        let lenient = Toro::parse("Check for Tables #10–#14", Mode::Lenient).unwrap();
+        assert_eq!(lenient.tables, TableSelector::Range(10, 14));
+        assert!(TableSelector::None.ids(3).is_empty());
+        assert!(TableSelector::All.ids(0).is_empty());
+    }
+
+    #[test]
+    fn test_quantities() {
+        let kind = |s| {
+            let err = Toro::from_toro_string(s).unwrap_err();
+            err.downcast::<syntax::ParseError>().unwrap().kind
+        };
+        assert_eq!(kind("new order for table 1: a * 0"), syntax::ErrorKind::NotPositive);
+        assert_eq!(kind("cancel for table 1: a * 1, b * -5"), syntax::ErrorKind::NotPositive);
+        assert_eq!(kind("new order for table 1: a * x"), syntax::ErrorKind::Syntax);
+        assert_eq!(kind("restock: a * 1000001"), syntax::ErrorKind::TooLarge);
+        assert_eq!(
+            kind("new order for table 1: a * 9223372036854775807, a * 1"),
+            syntax::ErrorKind::TooLarge
+        );
+        assert_eq!(
+            kind("new order for table 1: a * 1 with sets s * 1000001"),
+            syntax::ErrorKind::TooLarge
+        );
+        assert_eq!(
+            kind("new order for table 1: a * 99999999999999999999"),
+            syntax::ErrorKind::TooLarge
+        );
+        assert_eq!(
+            kind("cancel for table 1: a * -99999999999999999999"),
+            syntax::ErrorKind::NotPositive
+        );
+        assert_eq!(
+            kind("new order for table 1: a * 600000, b * 1, a * 400001"),
+            syntax::ErrorKind::TooLarge
+        );
+        assert!(Toro::from_toro_string("restock: a * 1000000").is_ok());
+        assert!(Toro::from_toro_string("restock: a * 600000, a * 400000").is_ok());
+        let sets = "new order for table 1: a * 1000000 with sets a * 1";
+        assert!(Toro::from_toro_string(sets).is_ok());
+        assert!(Toro::parse("restock: a x -1", Mode::Lenient).is_err());
+        let stored = Toro::from_stored_string("new order for table 1: a * -5").unwrap();
+        assert_eq!(stored.param, Some(MenuQuantities(vec![("a".into(), -5)])));
+        assert!(Toro::from_stored_string("new order for table 1: a * 1000001").is_ok());
+    }
+
+    #[test]
+    fn test_courses() {
+        let toro = Toro::from_toro_string(NEW_ORDER_HOLD).unwrap();
+        assert_eq!(toro.hold, vec!["mains", "desserts"]);
+        assert!(matches!(toro.param, Some(MenuQuantities(v)) if v.len() == 2));
+        let toro = Toro::from_toro_string(FIRE).unwrap();
+        assert!(matches!(toro.command, Fire));
+        assert_eq!(toro.course.as_deref(), Some("mains"));
+        assert_eq!(toro.tables, TableSelector::One(1));
+        assert!(Toro::from_toro_string("new order for table 1: a * 1 hold ").is_err());
+        assert!(Toro::from_toro_string("cancel for table 1: a * 1 hold mains").is_err());
+        assert!(Toro::from_toro_string("fire course mains").is_err());
+        assert!(Toro::from_toro_string("fire course for table 1").is_err());
+        assert!(Toro::from_toro_string("fire course mains for table 1: a").is_err());
+    }
+
+    #[test]
+    fn test_sets() {
+        let toro = Toro::from_toro_string(NEW_ORDER_SETS).unwrap();
+        assert!(matches!(toro.param, Some(MenuQuantities(ref v)) if v.len() == 3));
+        assert_eq!(toro.sets, vec![("a b set".to_string(), 2)]);
+        assert_eq!(toro.hold, vec!["mains"]);
+        let toro = Toro::from_toro_string("cancel for table 1: a * 1 with sets s * 1 because x");
+        assert_eq!(toro.unwrap().reason.as_deref(), Some("x"));
+        assert!(Toro::from_toro_string("new order for table 1: a * 1 with sets s").is_err());
+        assert!(Toro::from_toro_string("new order for table 1: a * 1 with sets s * 0").is_err());
+        assert!(Toro::from_toro_string("check for table 1: a with sets s * 1").is_err());
+        assert!(Toro::from_toro_string("new order for table 1: a with sets * 1").is_err());
+    }
+
+    #[test]
+    fn test_lenient() {
+        let lenient = |s| Toro::parse(s, Mode::Lenient).unwrap().to_toro_string();
+        assert_eq!(
+            lenient("New  Order for Table #1 :A x 1,b X2 ,  c  c c×3"),
+            "new order for table 1: a * 1, b * 2, c c c * 3"
+        );
+        assert_eq!(
+            lenient("CANCEL for table 1: box x 2, a*1 Because Too  Slow"),
+            "cancel for table 1: box * 2, a * 1 because Too Slow"
+        );
+        assert_eq!(
+            lenient("new order for table 1: a x 1 HOLD Mains"),
+            "new order for table 1: a * 1 hold mains"
+        );
+        assert_eq!(lenient("Fire Course mains for table #2"), "fire course mains for table 2");
+        assert_eq!(lenient(" YEET "), "yeet");
+        assert_eq!(lenient("Set Price: A at 120"), "set price: a at 120");
+        assert_eq!(lenient("Check for Table 1: Ramen, Tea"), "check for table 1: ramen, tea");
+        assert!(Toro::parse("New Order for Table #1: a x 1", Mode::Strict).is_err());
+
+        assert_eq!("Lenient".parse::<Mode>().unwrap(), Mode::Lenient);
+        assert!("loose".parse::<Mode>().is_err());
+    }
+
+    #[test]
+    fn test_keywords() {
+        let japanese = Keywords {
+            for_table: "テーブル".into(),
+            params: "：".into(),
+            list: "、".into(),
+            times: "×".into(),
+            because: " 理由 ".into(),
+            ..Keywords::default()
+        }
+        .with_command(New, "注文")
+        .with_command(Cancel, "取消")
+        .with_command(Fire, "開始");
+        let order = "注文テーブル1：ラーメン×2、お茶×1";
+        let toro = Toro::parse_with(order, Mode::Strict, &japanese).unwrap();
+        assert_eq!(toro.to_toro_string(), "new order for table 1: ラーメン * 2, お茶 * 1");
+        assert_eq!(toro.to_toro_string_in(&japanese), order);
+
+        let cancel = "取消テーブル2：お茶×1 理由 冷めた";
+        let toro = Toro::parse_with(cancel, Mode::Strict, &japanese).unwrap();
+        assert_eq!(toro.reason.as_deref(), Some("冷めた"));
+        assert_eq!(toro.to_toro_string_in(&japanese), cancel);
+        let toro = Toro::parse_with("開始 mains テーブル3", Mode::Strict, &japanese).unwrap();
+        assert_eq!(toro.to_toro_string(), "fire course mains for table 3");
+        // Commands not in the dictionary stay English
+        let toro = Toro::parse_with("yeet", Mode::Strict, &japanese).unwrap();
+        assert!(matches!(toro.command, Yeet));
+        assert!(Toro::parse_with(NEW_ORDER, Mode::Strict, &japanese).is_err());
+
+        let sloppy = "注文 テーブル #1 ： ラーメン x 2";
+        let toro = Toro::parse_with(sloppy, Mode::Lenient, &japanese).unwrap();
+        assert_eq!(toro.to_toro_string_in(&japanese), "注文テーブル1：ラーメン×2");
+        assert_eq!(Command::from_phrase(" 取消 ", &japanese).unwrap(), Cancel);
+    }
+
     #[test]
     fn test_serde() {
         let de_str = Toro::from_toro_string(NEW_ORDER).unwrap().to_toro_string();
         assert_eq!(NEW_ORDER, de_str);
         let de_str = Toro::from_toro_string(CANCEL).unwrap().to_toro_string();
         assert_eq!(CANCEL, de_str);
+        let de_str = Toro::from_toro_string(CANCEL_BECAUSE).unwrap().to_toro_string();
+        assert_eq!(CANCEL_BECAUSE, de_str);
+        let de_str = Toro::from_toro_string(NEW_ORDER_HOLD).unwrap().to_toro_string();
+        assert_eq!(NEW_ORDER_HOLD, de_str);
+        let de_str = Toro::from_toro_string(NEW_ORDER_SETS).unwrap().to_toro_string();
+        assert_eq!(NEW_ORDER_SETS, de_str);
+        let de_str = Toro::from_toro_string(FIRE).unwrap().to_toro_string();
+        assert_eq!(FIRE, de_str);
         let de_str = Toro::from_toro_string(CHECK).unwrap().to_toro_string();
         assert_eq!(CHECK, de_str);
         let de_str = Toro::from_toro_string(CHECK_ALL).unwrap().to_toro_string();
         assert_eq!(CHECK_ALL, de_str);
         let de_str = Toro::from_toro_string(YEET).unwrap().to_toro_string();
         assert_eq!(YEET, de_str);
+        let de_str = Toro::from_toro_string(CHECK_KITCHEN).unwrap().to_toro_string();
+        assert_eq!(CHECK_KITCHEN, de_str);
+        let de_str = Toro::from_toro_string(RESTOCK).unwrap().to_toro_string();
+        assert_eq!(RESTOCK, de_str);
+        let de_str = Toro::from_toro_string(SOLD_OUT).unwrap().to_toro_string();
+        assert_eq!(SOLD_OUT, de_str);
+        for edit in MENU_EDITS {
+            assert_eq!(Toro::from_toro_string(edit).unwrap().to_toro_string(), edit);
+        }
+    }
+
+    #[test]
+    fn test_command_display() {
+        for c in [
+            New,
+            Cancel,
+            Check,
+            CheckKitchen,
+            Restock,
+            SoldOut,
+            AddMenu,
+            RenameMenu,
+            RetireMenu,
+            SetPrice,
+            Fire,
+            Yeet,
+        ] {
+            assert_eq!(c.to_string().parse::<Command>().unwrap().to_string(), c.to_string());
+        }
     }
 
     #[test]
@@ -313,5 +858,18 @@ mod tests {
         assert!(Toro::from_toro_string("new order: name").is_err());
         assert!(Toro::from_toro_string("cancel: name").is_err());
         assert!(Toro::from_toro_string("yeet for table 1: name").is_err());
+        assert!(Toro::from_toro_string("check kitchen for table 1").is_err());
+        assert!(Toro::from_toro_string("check kitchen: name").is_err());
+        assert!(Toro::from_toro_string("restock: name").is_err());
+        assert!(Toro::from_toro_string("restock for table 1: a * 1").is_err());
+        assert!(Toro::from_toro_string("sold out: a * 1").is_err());
+        assert!(Toro::from_toro_string("sold out").is_err());
+        assert!(Toro::from_toro_string("add menu: a * 1").is_err());
+        assert!(Toro::from_toro_string("add menu for table 1: a").is_err());
+        assert!(Toro::from_toro_string("rename menu: a").is_err());
+        assert!(Toro::from_toro_string("rename menu: a to ").is_err());
+        assert!(Toro::from_toro_string("set price: a at cheap").is_err());
+        assert!(Toro::from_toro_string("set price: a").is_err());
+        assert!(Toro::from_toro_string("set price: a at -1").is_err());
     }
 }
@@ -0,0 +1,743 @@
+//! The grammar of TORO and the parser that follows it.
+//!
+//! Written with the English keywords. A [`Keywords`] dictionary swaps every quoted word.
+//!
+//! ```ebnf
+//! order         = blank , head , [ ":" , tail ] , blank ;
+//! head          = ( fire | command ) , [ blank , tables ] ;
+//! fire          = "fire course" , course ;
+//! command       = "new order" | "cancel" | "check" | "check kitchen" | "restock"
+//!               | "sold out" | "add menu" | "rename menu" | "retire menu" | "set price"
+//!               | "yeet" ;
+//! tables        = "for table" , blank , table id
+//!               | "for tables" , blank , table id , blank , "-" , blank , table id
+//!               | "for all tables" ;
+//! table id      = digit , { digit } ;
+//! tail          = params , [ " with sets " , menu quantity , { "," , menu quantity } ]
+//!               , [ " hold " , course , { "," , course } ] , [ " because " , reason ] ;
+//! params        = items | rename | prices ;
+//! items         = menu quantity , { "," , menu quantity }
+//!               | name , { "," , name } ;
+//! menu quantity = name , "*" , quantity ;
+//! rename        = name , " to " , name ;
+//! prices        = name , " at " , price , { "," , name , " at " , price } ;
+//! quantity      = blank , [ "-" ] , digit , { digit } , blank ;
+//! price         = blank , digit , { digit } , blank ;
+//! course        = name ;
+//! name          = blank , text , blank ;
+//! reason        = blank , text , blank ;
+//! text          = ? anything but blank at either end ? ;
+//! blank         = { ? white space ? } ;
+//! ```
+//!
+//! - The head ends at the first `:`, the params at the first ` because `, then ` hold `,
+//!   then ` with sets `.
+//! - `rename` is only for rename menu and `prices` only for set price.
+//!   Every other command takes `items`, where either all or none of the menus have a quantity.
+//! - The tables start at the first `for ...` words in the head.
+//!   A range never goes backward.
+//! - `:`, `,`, `*`, `-` and the `for ...` words may have any blank around them.
+//!   ` hold `, ` because `, ` with sets `, ` to ` and ` at ` are matched with exactly one space
+//!   on each side.
+//! - With a space put on both sides, a name never has ` hold `, ` because ` or ` with sets `
+//!   in it.
+//!   The name before ` to ` or ` at ` doesn't have that word either.
+//!   That way a name reads the same when the order is written back.
+//! - Which commands need a table, params, a reason, held courses or sets is checked after
+//!   parsing, see [`Toro`]. The service fills in the sets, see `expand_sets` in the app.
+//! - So is that every quantity is more than zero and that each menu adds up to at most
+//!   [`MAX_QUANTITY`], see [`Ast::check_quantities`].
+//!   Orders stored before that was checked may have any quantity.
+
+use std::{cmp::Reverse, collections::HashMap, fmt::Display, str::FromStr};
+
+use crate::{Command, Keywords, MenuName, Param, Price, Quantity, TableSelector, Toro};
+
+// Nobody orders, cancels or restocks more than this of one menu at once.
+// Keeps whatever adds quantities up far from overflowing.
+pub const MAX_QUANTITY: Quantity = 1_000_000;
+
+// Where something is in the parsed string, in bytes. The end is excluded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    // Doesn't follow the grammar
+    Syntax,
+    // A quantity of zero or less
+    NotPositive,
+    // A quantity, or the total of a menu, over `MAX_QUANTITY`
+    TooLarge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub span: Span,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}", self.message, self.span)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Params {
+    MenuQuantities(Vec<(Spanned<MenuName>, Spanned<Quantity>)>),
+    Menu(Vec<Spanned<MenuName>>),
+    Rename(Spanned<MenuName>, Spanned<MenuName>),
+    Prices(Vec<(Spanned<MenuName>, Spanned<Price>)>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ast {
+    pub command: Spanned<Command>,
+    // Only for fire
+    pub course: Option<Spanned<String>>,
+    pub tables: Option<Spanned<TableSelector>>,
+    pub params: Option<Spanned<Params>>,
+    pub hold: Vec<Spanned<String>>,
+    pub reason: Option<Spanned<String>>,
+    pub sets: Vec<(Spanned<MenuName>, Spanned<Quantity>)>,
+}
+
+impl Ast {
+    // Nobody orders, cancels or restocks zero or less of something, or too much of it.
+    // A menu listed more than once counts with its total.
+    pub fn check_quantities(&self) -> Result<(), ParseError> {
+        let items = match self.params.as_ref().map(|p| &p.node) {
+            Some(Params::MenuQuantities(items)) => items,
+            _ => return Ok(()),
+        };
+        let out_of_range = |(_, q): &&(_, Spanned<Quantity>)| !(1..=MAX_QUANTITY).contains(&q.node);
+        match items.iter().chain(self.sets.iter()).find(out_of_range) {
+            Some((menu, quantity)) if quantity.node <= 0 => Err(ParseError {
+                kind: ErrorKind::NotPositive,
+                message: format!("{} * {} is not more than zero", menu.node, quantity.node),
+                span: quantity.span,
+            }),
+            Some((menu, quantity)) => Err(ParseError {
+                kind: ErrorKind::TooLarge,
+                message: format!(
+                    "{} * {} is more than {}",
+                    menu.node, quantity.node, MAX_QUANTITY
+                ),
+                span: quantity.span,
+            }),
+            None => Ok(()),
+        }?;
+        for list in [items, &self.sets] {
+            let mut totals: HashMap<&str, Quantity> = HashMap::new();
+            for (menu, quantity) in list.iter() {
+                let total = totals.entry(&menu.node).or_default();
+                *total += quantity.node;
+                if *total > MAX_QUANTITY {
+                    return Err(ParseError {
+                        kind: ErrorKind::TooLarge,
+                        message: format!(
+                            "{} adds up to {}, which is more than {}",
+                            menu.node, total, MAX_QUANTITY
+                        ),
+                        span: quantity.span,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn parse(input: &str, keywords: &Keywords) -> Result<Ast, ParseError> {
+    Parser { kw: keywords }.order(Text::new(input))
+}
+
+// The `items` rule on its own
+pub fn parse_items(input: &str, keywords: &Keywords) -> Result<Params, ParseError> {
+    let text = Text::new(input).trim();
+    if text.is_empty() {
+        return Err(text.error("Expected parameters"));
+    }
+    Parser { kw: keywords }.items(text)
+}
+
+// A piece of the input that remembers where it was
+#[derive(Debug, Clone, Copy)]
+struct Text<'a> {
+    str: &'a str,
+    start: usize,
+}
+
+impl<'a> Text<'a> {
+    fn new(str: &'a str) -> Self {
+        Self { str, start: 0 }
+    }
+
+    fn span(self) -> Span {
+        Span {
+            start: self.start,
+            end: self.start + self.str.len(),
+        }
+    }
+
+    fn is_empty(self) -> bool {
+        self.str.is_empty()
+    }
+
+    fn trim(self) -> Self {
+        let trimmed = self.str.trim_start();
+        Self {
+            str: trimmed.trim_end(),
+            start: self.start + self.str.len() - trimmed.len(),
+        }
+    }
+
+    fn split_at(self, at: usize) -> (Self, Self) {
+        let (left, right) = self.str.split_at(at);
+        (
+            Self {
+                str: left,
+                start: self.start,
+            },
+            Self {
+                str: right,
+                start: self.start + at,
+            },
+        )
+    }
+
+    fn split_once(self, separator: &str) -> Option<(Self, Self)> {
+        let at = self.str.find(separator)?;
+        let (left, right) = self.split_at(at);
+        Some((left, right.split_at(separator.len()).1))
+    }
+
+    fn split(self, separator: &str) -> Vec<Self> {
+        if separator.is_empty() {
+            return vec![self];
+        }
+        let mut parts = Vec::new();
+        let mut rest = self;
+        while let Some((part, after)) = rest.split_once(separator) {
+            parts.push(part);
+            rest = after;
+        }
+        parts.push(rest);
+        parts
+    }
+
+    fn spanned<T>(self, node: T) -> Spanned<T> {
+        Spanned {
+            node,
+            span: self.span(),
+        }
+    }
+
+    fn error(self, message: impl Into<String>) -> ParseError {
+        ParseError {
+            kind: ErrorKind::Syntax,
+            message: message.into(),
+            span: self.span(),
+        }
+    }
+}
+
+struct Parser<'k> {
+    kw: &'k Keywords,
+}
+
+impl Parser<'_> {
+    // order = blank , head , [ ":" , tail ] , blank
+    fn order(&self, input: Text) -> Result<Ast, ParseError> {
+        let input = input.trim();
+        let (head, tail) = match input.split_once(self.kw.params.trim()) {
+            Some((head, tail)) => (head, Some(tail)),
+            None => (input, None),
+        };
+        let mut ast = self.head(head)?;
+        if let Some(tail) = tail {
+            self.tail(&mut ast, tail)?;
+        }
+        Ok(ast)
+    }
+
+    // head = ( fire | command ) , [ blank , tables ]
+    fn head(&self, head: Text) -> Result<Ast, ParseError> {
+        let head = head.trim();
+        if head.is_empty() {
+            return Err(head.error("Expected a command"));
+        }
+        let (command, tables) = self.tables(head)?;
+        let command = command.trim();
+        let fire = self.kw.command(Command::Fire);
+        let (command, course) = match command.str.strip_prefix(fire) {
+            // fire = "fire course" , course
+            Some(_) => {
+                let (fire, course) = command.split_at(fire.len());
+                (
+                    fire.spanned(Command::Fire),
+                    Some(self.name(course, "course", None)?),
+                )
+            }
+            None => match Command::from_phrase(command.str, self.kw) {
+                Ok(c) => (command.spanned(c), None),
+                Err(_) => return Err(command.error(format!("Unknown command {:?}", command.str))),
+            },
+        };
+        Ok(Ast {
+            command,
+            course,
+            tables,
+            params: None,
+            hold: Vec::new(),
+            reason: None,
+            sets: Vec::new(),
+        })
+    }
+
+    // tables = "for table" , blank , table id
+    //        | "for tables" , blank , table id , blank , "-" , blank , table id
+    //        | "for all tables"
+    // Splits them off the head. At the same place, the longest words win.
+    fn tables<'a>(
+        &self,
+        head: Text<'a>,
+    ) -> Result<(Text<'a>, Option<Spanned<TableSelector>>), ParseError> {
+        let words = [
+            self.kw.for_table.trim(),
+            self.kw.for_tables.trim(),
+            self.kw.for_all_tables.trim(),
+        ];
+        let found = words
+            .iter()
+            .enumerate()
+            .filter_map(|(i, w)| Some((head.str.find(w)?, Reverse(w.len()), i)))
+            .min();
+        let (at, length, which) = match found {
+            Some((at, Reverse(length), which)) => (at, length, which),
+            None => return Ok((head, None)),
+        };
+        let (command, tables) = head.split_at(at);
+        let rest = tables.split_at(length).1;
+        let selector = match which {
+            0 => TableSelector::One(number(rest, "a table number", false)?.node),
+            1 => {
+                let range = self.kw.range.trim();
+                let (first, last) = rest.split_once(range).ok_or_else(|| {
+                    rest.trim()
+                        .error(format!("Expected <table>{}<table>", range))
+                })?;
+                let first = number(first, "a table number", false)?;
+                let last = number(last, "a table number", false)?;
+                if first.node > last.node {
+                    return Err(tables.trim().error("The first table goes first"));
+                }
+                TableSelector::Range(first.node, last.node)
+            }
+            _ if !rest.trim().is_empty() => {
+                return Err(rest.trim().error("Expected nothing after all tables"));
+            }
+            _ => TableSelector::All,
+        };
+        Ok((command, Some(tables.trim().spanned(selector))))
+    }
+
+    // tail = params , [ " with sets " , menu quantity , { "," , menu quantity } ]
+    //      , [ " hold " , course , { "," , course } ] , [ " because " , reason ]
+    fn tail(&self, ast: &mut Ast, tail: Text) -> Result<(), ParseError> {
+        let (params, reason) = match tail.split_once(&self.kw.because) {
+            Some((params, reason)) => (params, Some(reason)),
+            None => (tail, None),
+        };
+        let (params, hold) = match params.split_once(&self.kw.hold) {
+            Some((params, hold)) => (params, Some(hold)),
+            None => (params, None),
+        };
+        let (params, sets) = match params.split_once(&self.kw.sets) {
+            Some((params, sets)) => (params, Some(sets)),
+            None => (params, None),
+        };
+        let params = params.trim();
+        if params.is_empty() {
+            return Err(params.error("Expected parameters"));
+        }
+        let node = match ast.command.node {
+            Command::RenameMenu => self.rename(params)?,
+            Command::SetPrice => self.prices(params)?,
+            _ => self.items(params)?,
+        };
+        ast.params = Some(params.spanned(node));
+        if let Some(sets) = sets {
+            let sets = sets.trim();
+            ast.sets = match self.items(sets)? {
+                Params::MenuQuantities(sets) => sets,
+                _ => return Err(sets.error("Expected <set> * <quantity>")),
+            };
+        }
+        if let Some(hold) = hold {
+            ast.hold = hold
+                .split(self.kw.list.trim())
+                .into_iter()
+                .map(|c| self.name(c, "course", None))
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(reason) = reason {
+            let reason = reason.trim();
+            if reason.is_empty() {
+                return Err(reason.error("Because of what?"));
+            }
+            ast.reason = Some(reason.spanned(reason.str.to_string()));
+        }
+        Ok(())
+    }
+
+    // items = menu quantity , { "," , menu quantity } | name , { "," , name }
+    fn items(&self, text: Text) -> Result<Params, ParseError> {
+        let times = self.kw.times.trim();
+        let entries = text.split(self.kw.list.trim());
+        if !text.str.contains(times) {
+            let menus = entries
+                .into_iter()
+                .map(|m| self.name(m, "menu", None))
+                .collect::<Result<_, _>>()?;
+            return Ok(Params::Menu(menus));
+        }
+        let mut items = Vec::new();
+        for entry in entries {
+            // menu quantity = name , "*" , quantity
+            match entry.split_once(times) {
+                Some((menu, amount)) => items.push((
+                    self.name(menu, "menu", None)?,
+                    quantity(amount)?,
+                )),
+                None => {
+                    let menu = self.name(entry, "menu", None)?;
+                    let message = format!("{:?} needs a quantity like the other menus", menu.node);
+                    return Err(entry.trim().error(message));
+                }
+            }
+        }
+        Ok(Params::MenuQuantities(items))
+    }
+
+    // rename = name , " to " , name
+    fn rename(&self, text: Text) -> Result<Params, ParseError> {
+        let (from, to) = text
+            .split_once(&self.kw.to)
+            .ok_or_else(|| text.error(format!("Expected <menu>{}<menu>", self.kw.to)))?;
+        Ok(Params::Rename(
+            self.name(from, "menu", Some(&self.kw.to))?,
+            self.name(to, "menu", None)?,
+        ))
+    }
+
+    // prices = name , " at " , price , { "," , name , " at " , price }
+    fn prices(&self, text: Text) -> Result<Params, ParseError> {
+        let prices = text
+            .split(self.kw.list.trim())
+            .into_iter()
+            .map(|entry| {
+                let (menu, price) = entry.split_once(&self.kw.at).ok_or_else(|| {
+                    entry
+                        .trim()
+                        .error(format!("Expected <menu>{}<price>", self.kw.at))
+                })?;
+                Ok((
+                    self.name(menu, "menu", Some(&self.kw.at))?,
+                    number(price, "a price", false)?,
+                ))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Params::Prices(prices))
+    }
+
+    // name = blank , text , blank
+    fn name(
+        &self,
+        text: Text,
+        what: &str,
+        word: Option<&str>,
+    ) -> Result<Spanned<String>, ParseError> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(text.error(format!("Expected a {}", what)));
+        }
+        let padded = format!(" {} ", text.str);
+        let words = [
+            self.kw.hold.as_str(),
+            self.kw.because.as_str(),
+            self.kw.sets.as_str(),
+        ];
+        if let Some(word) = words.into_iter().chain(word).find(|w| padded.contains(w)) {
+            let message = format!("A {} can't have {:?} in it", what, word.trim());
+            return Err(text.error(message));
+        }
+        Ok(text.spanned(text.str.to_string()))
+    }
+}
+
+// number = blank , [ "-" ] , digit , { digit } , blank
+fn number<T: FromStr>(text: Text, what: &str, signed: bool) -> Result<Spanned<T>, ParseError> {
+    let text = text.trim();
+    let digits = match text.str.strip_prefix('-') {
+        Some(digits) if signed => digits,
+        _ => text.str,
+    };
+    if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+        if let Ok(n) = text.str.parse() {
+            return Ok(text.spanned(n));
+        }
+    }
+    Err(text.error(format!("Expected {} but got {:?}", what, text.str)))
+}
+
+// quantity = number
+// Too many digits for a `Quantity` is still a quantity, just out of range,
+// so it gets the same kind of error as in `Ast::check_quantities`
+fn quantity(text: Text) -> Result<Spanned<Quantity>, ParseError> {
+    number(text, "a quantity", true).map_err(|e| {
+        let text = text.trim();
+        let (digits, kind) = match text.str.strip_prefix('-') {
+            Some(digits) => (digits, ErrorKind::NotPositive),
+            None => (text.str, ErrorKind::TooLarge),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return e;
+        }
+        ParseError {
+            kind,
+            message: format!("{} is out of range", text.str),
+            ..e
+        }
+    })
+}
+
+impl From<Params> for Param {
+    fn from(params: Params) -> Self {
+        let node = |s: Spanned<String>| s.node;
+        match params {
+            Params::MenuQuantities(v) => {
+                Param::MenuQuantities(v.into_iter().map(|(m, q)| (m.node, q.node)).collect())
+            }
+            Params::Menu(v) => Param::Menu(v.into_iter().map(node).collect()),
+            Params::Rename(from, to) => Param::Rename(from.node, to.node),
+            Params::Prices(v) => {
+                Param::Prices(v.into_iter().map(|(m, p)| (m.node, p.node)).collect())
+            }
+        }
+    }
+}
+
+impl From<Ast> for Toro {
+    fn from(ast: Ast) -> Self {
+        Toro {
+            command: ast.command.node,
+            tables: ast.tables.map(|t| t.node).unwrap_or_default(),
+            param: ast.params.map(|p| p.node.into()),
+            reason: ast.reason.map(|r| r.node),
+            hold: ast.hold.into_iter().map(|c| c.node).collect(),
+            course: ast.course.map(|c| c.node),
+            sets: ast.sets.into_iter().map(|(m, q)| (m.node, q.node)).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Mode;
+    use proptest::{collection::vec, option, prelude::*};
+
+    fn span(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    #[test]
+    fn test_spans() {
+        let ast = parse(
+            "new order for table 12: a * 1, c c * 3",
+            &Keywords::default(),
+        )
+        .unwrap();
+        assert_eq!(ast.command.span, span(0, 9));
+        assert_eq!(
+            ast.tables.map(|t| (t.node, t.span)),
+            Some((TableSelector::One(12), span(10, 22)))
+        );
+        assert_eq!(ast.params.as_ref().unwrap().span, span(24, 38));
+        match ast.params.unwrap().node {
+            Params::MenuQuantities(items) => {
+                assert_eq!(items[1].0.node, "c c");
+                assert_eq!(items[1].0.span, span(31, 34));
+                assert_eq!(items[1].1.span, span(37, 38));
+            }
+            _ => panic!("wrong params"),
+        }
+        let ast = parse(" fire course mains for table 1", &Keywords::default()).unwrap();
+        assert_eq!(ast.command.span, span(1, 12));
+        assert_eq!(ast.course.unwrap().span, span(13, 18));
+    }
+
+    #[test]
+    fn test_errors() {
+        let kw = Keywords::default();
+        let error = parse("new order for table 1: a * 1, b", &kw).unwrap_err();
+        assert_eq!(error.span, span(30, 31));
+        assert_eq!(
+            error.to_string(),
+            "\"b\" needs a quantity like the other menus at 30..31"
+        );
+        assert_eq!(
+            parse("new order for table 1: a, , b", &kw)
+                .unwrap_err()
+                .span,
+            span(26, 26)
+        );
+        assert_eq!(
+            parse("dance for table 1", &kw).unwrap_err().span,
+            span(0, 5)
+        );
+        assert_eq!(
+            parse("check for table +1", &kw).unwrap_err().span,
+            span(16, 18)
+        );
+        assert_eq!(parse("restock: a * x", &kw).unwrap_err().span, span(13, 14));
+        // These would read differently when written back
+        assert!(parse("new order for table 1:hold * 1", &kw).is_err());
+        assert!(parse("new order for table 1: a hold* 1", &kw).is_err());
+        assert!(parse("rename menu:to a to b", &kw).is_err());
+        assert!(parse("set price: a, b at 1", &kw).is_err());
+        // Only the name before the keyword can't have it
+        assert!(parse("rename menu: a to b to c", &kw).is_ok());
+        assert!(parse("cancel for table 1: a * 1 because hold because", &kw).is_ok());
+    }
+
+    fn word() -> impl Strategy<Value = String> {
+        "[a-z]{1,6}".prop_filter("keyword", |w| {
+            !["hold", "because", "with", "to", "at"].contains(&&**w)
+        })
+    }
+
+    fn name() -> impl Strategy<Value = String> {
+        vec(word(), 1..4).prop_map(|words| words.join(" "))
+    }
+
+    fn tables() -> impl Strategy<Value = TableSelector> {
+        prop_oneof![
+            (0..99usize).prop_map(TableSelector::One),
+            (0..99usize, 0..9usize).prop_map(|(t, n)| TableSelector::Range(t, t + n)),
+            Just(TableSelector::All),
+        ]
+    }
+
+    fn toro(command: Command, tables: TableSelector, param: Option<Param>) -> Toro {
+        Toro {
+            command,
+            tables,
+            param,
+            reason: None,
+            hold: Vec::new(),
+            course: None,
+            sets: Vec::new(),
+        }
+    }
+
+    fn any_toro() -> impl Strategy<Value = Toro> {
+        use Command::*;
+        use TableSelector::{None as Restaurant, One};
+        let items = || vec((name(), 1..100i64), 1..4).prop_map(Param::MenuQuantities);
+        let sets = || vec((name(), 1..100i64), 0..3);
+        let menus = || vec(name(), 1..4).prop_map(Param::Menu);
+        let reason = "[a-z:*,]([a-z :*,]{0,12}[a-z:*,])?";
+        prop_oneof![
+            (0..99usize, items(), vec(word(), 0..3), sets()).prop_map(|(t, items, hold, sets)| {
+                Toro {
+                    hold,
+                    sets,
+                    ..toro(New, One(t), Some(items))
+                }
+            }),
+            (tables(), items(), option::of(reason), sets()).prop_map(|(t, items, reason, sets)| {
+                Toro {
+                    reason,
+                    sets,
+                    ..toro(Cancel, t, Some(items))
+                }
+            }),
+            (tables(), option::of(prop_oneof![items(), menus()]))
+                .prop_map(|(t, param)| toro(Check, t, param)),
+            (tables(), word()).prop_map(|(t, course)| Toro {
+                course: Some(course),
+                ..toro(Fire, t, None)
+            }),
+            items().prop_map(|items| toro(Restock, Restaurant, Some(items))),
+            menus().prop_map(|menus| toro(SoldOut, Restaurant, Some(menus))),
+            menus().prop_map(|menus| toro(AddMenu, Restaurant, Some(menus))),
+            menus().prop_map(|menus| toro(RetireMenu, Restaurant, Some(menus))),
+            (name(), name()).prop_map(|(from, to)| {
+                toro(RenameMenu, Restaurant, Some(Param::Rename(from, to)))
+            }),
+            vec((name(), any::<Price>()), 1..4).prop_map(|prices| toro(
+                SetPrice,
+                Restaurant,
+                Some(Param::Prices(prices))
+            )),
+            Just(toro(CheckKitchen, Restaurant, None)),
+            Just(toro(Yeet, Restaurant, None)),
+        ]
+    }
+
+    // Bits of orders thrown together, so some of them parse
+    const PIECES: &str = "(new order|cancel|check|fire course|restock|set price|rename menu\
+        |sold out|for table|for tables|for all tables|hold|because|to|at|[ :,*#x0-9a-c–-]){0,16}";
+
+    proptest! {
+        #[test]
+        fn test_print_then_parse(toro in any_toro()) {
+            let printed = toro.to_toro_string();
+            prop_assert_eq!(Toro::from_toro_string(&printed).unwrap(), toro.clone());
+
+            let japanese = Keywords {
+                for_table: "テーブル".into(),
+                params: "：".into(),
+                list: "、".into(),
+                times: "×".into(),
+                because: " 理由 ".into(),
+                ..Keywords::default()
+            }
+            .with_command(Command::New, "注文");
+            let printed = toro.to_toro_string_in(&japanese);
+            let parsed = Toro::parse_with(&printed, Mode::Strict, &japanese).unwrap();
+            prop_assert_eq!(parsed, toro);
+        }
+
+        #[test]
+        fn test_parse_then_print(input in PIECES) {
+            for mode in [Mode::Strict, Mode::Lenient] {
+                if let Ok(toro) = Toro::parse(&input, mode) {
+                    let printed = toro.to_toro_string();
+                    prop_assert_eq!(Toro::from_toro_string(&printed).unwrap(), toro);
+                }
+            }
+        }
+    }
+}